@@ -1,27 +1,207 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
 use anchor_lang::solana_program::{program::invoke_signed, system_instruction};
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+use solana_sdk_ids::ed25519_program;
 use sha2::{Digest, Sha256};
-use std::str::FromStr;
 
 declare_id!("Gdy8jkUZqJkiWc7TGuQsou8TcQP7Nay9n6Xaie4vxKGj");
 
 // 1% rake = 100 basis points
 const DEFAULT_HOUSE_FEE_BPS: u16 = 100;
 const MAX_HOUSE_FEE_BPS: u16 = 1_000; // e.g. max 10%
+const MAX_RAKE_TIERS: usize = 4;
+const MAX_PAYEES: usize = 4;
+// Referrers can be given at most half of the rake their players generate.
+const MAX_REFERRAL_SHARE_BPS: u16 = 5_000;
+// Upper bound for the integrator fee cap the fee manager can configure (5%).
+const MAX_INTEGRATOR_FEE_BPS: u16 = 500;
 const BPS_DENOMINATOR: u64 = 10_000;
+// House fee changes only apply to games created at least ~1 day after they are executed.
+const HOUSE_FEE_TIMELOCK_SLOTS: u64 = 216_000;
 const MAX_ROUNDS: usize = 5;
+// Match roots cover 2^3 = 8 leaf slots; slot `i` holds round `i`'s commitment and
+// slots from MAX_ROUNDS on are zero.
+const MATCH_TREE_DEPTH: usize = 3;
+const _: () = assert!(
+    1 << MATCH_TREE_DEPTH >= MAX_ROUNDS && 1 << (MATCH_TREE_DEPTH - 1) < MAX_ROUNDS
+);
 const MIN_BET_LAMPORTS: u64 = 100_000_000;
+// A rake schedule's fee floor may not exceed the max rate on the smallest possible pot,
+// so `min_fee` can never lift the rake above `MAX_HOUSE_FEE_BPS` of a pot.
+const MAX_MIN_HOUSE_FEE: u64 = MIN_BET_LAMPORTS * 2 * MAX_HOUSE_FEE_BPS as u64 / BPS_DENOMINATOR;
 // Game timeout (e.g. if player2 never joins)
 // ~3 minutes at 400ms/slot = 180s / 0.4s = 450 slots
 const TIMEOUT_SLOTS: u64 = 450;
 // Estimated block time on Solana mainnet/devnet ~400ms. Used to map seconds to slots.
 const ESTIMATED_SLOT_MS: u64 = 400;
 const COMMIT_PHASE_MS: u64 = 30_000; // 30 seconds to allow for network latency and signing
-//const REVEAL_PHASE_MS: u64 = 5_000;
-// Convert the 5 second windows into slots (rounded up) so on-chain deadlines track block time.
-const COMMIT_PHASE_SLOTS: u64 = (COMMIT_PHASE_MS + ESTIMATED_SLOT_MS - 1) / ESTIMATED_SLOT_MS;
-//const REVEAL_PHASE_SLOTS: u64 = (REVEAL_PHASE_MS + ESTIMATED_SLOT_MS - 1) / ESTIMATED_SLOT_MS;
+const REVEAL_PHASE_MS: u64 = 30_000; // same allowance, from when both seats are committed
+// Convert the windows into slots (rounded up) so on-chain deadlines track block time.
+const COMMIT_PHASE_SLOTS: u64 = COMMIT_PHASE_MS.div_ceil(ESTIMATED_SLOT_MS);
+const REVEAL_PHASE_SLOTS: u64 = REVEAL_PHASE_MS.div_ceil(ESTIMATED_SLOT_MS);
+// House administration is an M-of-N admin set; privileged actions go through proposals.
+const MAX_ADMINS: usize = 10;
+// Proposals that don't gather enough approvals lapse after ~3 days (400ms slots).
+const PROPOSAL_EXPIRY_SLOTS: u64 = 648_000;
+// Players may pull their deposits out of a game that has been idle, or under a house
+// pause, this long, without any admin. ~2 days by default, configurable within 1..7 days.
+const DEFAULT_EMERGENCY_EXIT_DELAY_SLOTS: u64 = 432_000;
+const MIN_EMERGENCY_EXIT_DELAY_SLOTS: u64 = 216_000;
+const MAX_EMERGENCY_EXIT_DELAY_SLOTS: u64 = 1_512_000;
+// Circuit breakers in `HouseVault.paused_ops`. Refunds, timeouts and settlement
+// are never pausable so funds can always leave the program.
+const PAUSE_CREATE_GAME: u8 = 1 << 0;
+const PAUSE_JOIN_GAME: u8 = 1 << 1;
+const PAUSE_START_ROUND: u8 = 1 << 2;
+const PAUSABLE_OPS: u8 = PAUSE_CREATE_GAME | PAUSE_JOIN_GAME | PAUSE_START_ROUND;
+// Entry fees and rake are spread over this many fee shard PDAs (picked by game_id)
+// so that games don't all serialize on one writable house account.
+const FEE_SHARD_COUNT: u8 = 8;
+// What a session key may do on its player's behalf (`Game.session_perms_p*`).
+const SESSION_PERM_COMMIT: u8 = 1 << 0;
+const SESSION_PERM_REVEAL: u8 = 1 << 1;
+const SESSION_PERM_START_ROUND: u8 = 1 << 2;
+const SESSION_PERM_FORFEIT: u8 = 1 << 3;
+const SESSION_PERMS: u8 =
+    SESSION_PERM_COMMIT | SESSION_PERM_REVEAL | SESSION_PERM_START_ROUND | SESSION_PERM_FORFEIT;
+// Wallet-level sessions span all games, so they can never forfeit and are short-lived.
+const WALLET_SESSION_PERMS: u8 =
+    SESSION_PERM_COMMIT | SESSION_PERM_REVEAL | SESSION_PERM_START_ROUND;
+const MAX_WALLET_SESSION_SLOTS: u64 = 216_000; // ~24h at 400ms slots
+// After each disputed channel state lands, the other player has ~1 minute to answer
+// with a newer co-signed state before rounds continue on-chain.
+const CHANNEL_CHALLENGE_SLOTS: u64 = 150;
+
+/// Moves `amount` lamports out of one of the program's vault PDAs.
+///
+/// Vaults owned by this program are debited directly. Vaults created before the
+/// switch to program ownership are still system-owned and go through a signed
+/// system transfer instead.
+fn pay_from_vault<'info>(
+    amount: u64,
+    vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    if vault.owner == &crate::ID {
+        vault.sub_lamports(amount)?;
+        to.add_lamports(amount)?;
+        Ok(())
+    } else {
+        transfer_with_signer(amount, vault, to, system_program, signer_seeds)
+    }
+}
+
+/// Sweeps everything left in a vault PDA (rent reserve, dust) to `to` and closes it.
+///
+/// A zero-lamport account is purged by the runtime at the end of the transaction;
+/// handing ownership back to the system program keeps the address re-creatable.
+fn close_vault<'info>(
+    vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    pay_from_vault(vault.lamports(), vault, to, system_program, signer_seeds)?;
+
+    if vault.owner == &crate::ID {
+        vault.assign(&system_program::ID);
+    }
+
+    Ok(())
+}
+
+/// Fails unless `game_vault` still backs everything the game's records say it holds.
+fn require_escrow_healthy(game: &Game, game_vault: &AccountInfo) -> Result<()> {
+    let audit = game.audit_escrow(game_vault, &Rent::get()?);
+    require!(audit.healthy, RpsError::EscrowInvariantViolated);
+    Ok(())
+}
+
+/// Fails unless a closing game paid out exactly what it held in escrow and left
+/// `game_vault` empty.
+fn require_escrow_released(released: u64, escrowed: u64, game_vault: &AccountInfo) -> Result<()> {
+    require!(
+        released == escrowed && game_vault.lamports() == 0,
+        RpsError::EscrowInvariantViolated
+    );
+    Ok(())
+}
+
+/// Grows a program-owned account written under an older, shorter layout to `space`
+/// bytes, topping its rent up from `payer`. The new tail is zero-filled.
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    discriminator: &[u8],
+    space: usize,
+) -> Result<()> {
+    {
+        let data = account.try_borrow_data()?;
+        require!(
+            data.starts_with(discriminator),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        require!(data.len() < space, RpsError::NothingToMigrate);
+    }
+
+    let top_up = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if top_up > 0 {
+        let cpi_ctx = CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: payer.to_account_info(),
+                to: account.clone(),
+            },
+        );
+        system_program::transfer(cpi_ctx, top_up)?;
+    }
+
+    account.resize(space)?;
+    Ok(())
+}
+
+/// Checks that an admin proposal may be executed right now.
+fn require_approved(house_vault: &HouseVault, proposal: &Proposal) -> Result<()> {
+    require!(
+        proposal.admin_set_version == house_vault.admin_set_version,
+        RpsError::StaleProposal
+    );
+    require!(
+        Clock::get()?.slot <= proposal.expiry_slot,
+        RpsError::ProposalExpired
+    );
+    require!(
+        proposal.approvals.count_ones() >= house_vault.threshold as u32,
+        RpsError::NotEnoughApprovals
+    );
+    Ok(())
+}
+
+/// Fails if any of the `ops` circuit breakers is tripped.
+fn require_not_paused(house_vault: &HouseVault, ops: u8) -> Result<()> {
+    require!(house_vault.paused_ops & ops == 0, RpsError::Paused);
+    Ok(())
+}
+
+/// Fee shard a game's entry fees and rake are routed to.
+fn fee_shard_index(game_id: &[u8; 32]) -> u8 {
+    game_id[0] % FEE_SHARD_COUNT
+}
+
+/// Vault PDAs are program-owned, except for ones created before the ownership switch.
+fn is_vault_owner(owner: &Pubkey) -> bool {
+    owner == &crate::ID || owner == &system_program::ID
+}
 
 fn transfer_with_signer<'info>(
     amount: u64,
@@ -53,950 +233,5785 @@ fn transfer_with_signer<'info>(
 pub mod rps_game {
     use super::*;
 
+    /// Creates the house config and SOL vault.
+    ///
+    /// - Only the program's upgrade authority may run this (checked through the
+    ///   ProgramData account), and only once.
     pub fn init_house_vault(ctx: Context<InitHouseVault>) -> Result<()> {
-        // Only the authorized initializer may run this once
-        let authorized = Pubkey::from_str("FmeuxQMqMsR7WQz6jqbBcbAUcdRapFBttd6BqmtuHKPm")
-            .map_err(|_| error!(RpsError::Unauthorized))?;
-        require_keys_eq!(ctx.accounts.admin.key(), authorized, RpsError::Unauthorized);
-
         let vault = &mut ctx.accounts.house_vault;
         vault.bump = ctx.bumps.house_vault;
         vault.admin = ctx.accounts.admin.key();
+        vault.pending_admin = Pubkey::default();
         vault.house_fee_bps = DEFAULT_HOUSE_FEE_BPS;
+
+        // Bootstrap a 1-of-1 admin set; grow it through an `UpdateAdminSet` proposal.
+        vault.admins = [Pubkey::default(); MAX_ADMINS];
+        vault.admins[0] = ctx.accounts.admin.key();
+        vault.admin_count = 1;
+        vault.threshold = 1;
+        vault.admin_set_version = 0;
+        vault.proposal_count = 0;
+        vault.paused_ops = 0;
+        vault.paused_since_slot = 0;
+        vault.emergency_exit_delay_slots = DEFAULT_EMERGENCY_EXIT_DELAY_SLOTS;
+        vault.pending_house_fee_bps = 0;
+        vault.pending_fee_activation_slot = 0;
+        vault.rake_schedule = RakeSchedule::default();
+        vault.pending_rake_schedule = RakeSchedule::default();
+        vault.pending_rake_activation_slot = 0;
+        vault.referral_share_bps = 0;
+        vault.max_integrator_fee_bps = 0;
+        vault.payee_split = PayeeSplit::default();
+        vault.liabilities = 0;
+        vault.withdrawal_cap_per_epoch = 0;
+        vault.withdrawal_epoch = 0;
+        vault.withdrawn_this_epoch = 0;
+        vault.stats = HouseStats::default();
+        vault.fee_refund_policy = FeeRefundPolicy::default();
         Ok(())
     }
 
-    pub fn set_house_fee(ctx: Context<SetHouseFee>, new_fee_bps: u16) -> Result<()> {
-        let vault = &mut ctx.accounts.house_vault;
+    /// Sets how long a game must sit idle, or the house stay paused, before
+    /// `emergency_exit` unlocks.
+    ///
+    /// - Only the super-admin may call this; bounded to
+    ///   `MIN_EMERGENCY_EXIT_DELAY_SLOTS..=MAX_EMERGENCY_EXIT_DELAY_SLOTS`.
+    /// - Games snapshot the delay at creation, so existing games are unaffected.
+    pub fn set_emergency_exit_delay(
+        ctx: Context<SetHouseConfig>,
+        delay_slots: u64,
+    ) -> Result<()> {
+        require!(
+            (MIN_EMERGENCY_EXIT_DELAY_SLOTS..=MAX_EMERGENCY_EXIT_DELAY_SLOTS).contains(&delay_slots),
+            RpsError::InvalidEmergencyExitDelay
+        );
 
-        require_keys_eq!(ctx.accounts.admin.key(), vault.admin, RpsError::Unauthorized);
-        require!(new_fee_bps <= MAX_HOUSE_FEE_BPS, RpsError::InvalidHouseFee);
+        ctx.accounts.house_vault.emergency_exit_delay_slots = delay_slots;
+        Ok(())
+    }
 
-        vault.house_fee_bps = new_fee_bps;
+    /// Limits how many lamports may leave `house_vault_sol` per epoch through
+    /// withdrawals and distributions.
+    ///
+    /// - Only the super-admin may call this; `0` removes the cap.
+    pub fn set_withdrawal_cap(ctx: Context<SetHouseConfig>, cap_per_epoch: u64) -> Result<()> {
+        ctx.accounts.house_vault.withdrawal_cap_per_epoch = cap_per_epoch;
         Ok(())
     }
 
-    /// Player 1 creates the game and deposits entry fee + bet.
+    /// Earmarks house funds the house owes (house-banked games, jackpots, pending
+    /// rewards) so withdrawals and distributions leave them in the vault.
     ///
-    /// - `game_id` is a 32-byte identifier (e.g. uuid bytes or hash of it)
-    /// - `bet_amount` is per-player bet (lamports)
-    /// - `entry_fee` is per-player fee (lamports, non-refundable)
-    pub fn create_game(
-        ctx: Context<CreateGame>,
-        game_id: [u8; 32],
-        bet_amount: u64,
-        entry_fee: u64,
-    ) -> Result<()> {
-        // basic validation
-        require!(bet_amount > 0, RpsError::InvalidBetAmount);
-        require!(entry_fee > 0, RpsError::InvalidEntryFee);
+    /// - Only a treasurer may call this.
+    /// - The vault must hold rent-exempt minimum plus all liabilities afterwards.
+    pub fn commit_house_liability(ctx: Context<ManageLiabilities>, amount: u64) -> Result<()> {
+        require!(amount > 0, RpsError::InvalidLiabilityAmount);
 
-        // enforce min bet = 0.1 SOL
+        let house_vault_sol = ctx.accounts.house_vault_sol.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(house_vault_sol.data_len());
+        let vault = &mut ctx.accounts.house_vault;
+        let liabilities = vault
+            .liabilities
+            .checked_add(amount)
+            .ok_or(RpsError::MathOverflow)?;
+        let required = reserve
+            .checked_add(liabilities)
+            .ok_or(RpsError::MathOverflow)?;
         require!(
-            bet_amount >= MIN_BET_LAMPORTS,
-            RpsError::BetTooLow
-        );
-
-        // Player1 pays bet_amount into the per-game vault PDA
-        let cpi_ctx_bet = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.player1.to_account_info(),
-                to: ctx.accounts.game_vault.to_account_info(),
-            },
+            house_vault_sol.lamports() >= required,
+            RpsError::InsufficientHouseFunds
         );
-        system_program::transfer(cpi_ctx_bet, bet_amount)?;
-
-        // Player1 pays entry_fee directly into global house vault SOL PDA
-        let cpi_ctx_fee = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.player1.to_account_info(),
-                to: ctx.accounts.house_vault_sol.to_account_info(),
-            },
-        );
-        system_program::transfer(cpi_ctx_fee, entry_fee)?;
-
-        // Init game state
-        let game = &mut ctx.accounts.game;
+        vault.liabilities = liabilities;
 
-        game.bump = ctx.bumps.game;
-        game.game_id = game_id;
+        emit!(HouseLiabilitiesChangedEvent {
+            liabilities,
+            treasurer: ctx.accounts.treasurer.key(),
+        });
+        Ok(())
+    }
 
-        game.player1 = ctx.accounts.player1.key();
-        game.player2 = Pubkey::default();
+    /// Releases earmarked house funds once the obligation is paid or void.
+    ///
+    /// - Only a treasurer may call this.
+    pub fn release_house_liability(ctx: Context<ManageLiabilities>, amount: u64) -> Result<()> {
+        require!(amount > 0, RpsError::InvalidLiabilityAmount);
 
-        game.house_vault = ctx.accounts.house_vault.key();
+        let vault = &mut ctx.accounts.house_vault;
+        vault.liabilities = vault
+            .liabilities
+            .checked_sub(amount)
+            .ok_or(RpsError::InvalidLiabilityAmount)?;
+
+        emit!(HouseLiabilitiesChangedEvent {
+            liabilities: vault.liabilities,
+            treasurer: ctx.accounts.treasurer.key(),
+        });
+        Ok(())
+    }
 
-        game.session_p1 = Pubkey::default();
-        game.session_p2 = Pubkey::default();
+    /// Trips the circuit breakers in `ops` (see `PAUSE_*`); `PAUSABLE_OPS` is a full pause.
+    ///
+    /// - Callable by the super-admin or a pauser.
+    pub fn pause(ctx: Context<SetPause>, ops: u8) -> Result<()> {
+        require!(ops != 0 && ops & !PAUSABLE_OPS == 0, RpsError::InvalidPauseOps);
 
-        // store bet & entry fee in state
-        game.bet_amount = bet_amount;
-        game.entry_fee = entry_fee;
+        let vault = &mut ctx.accounts.house_vault;
+        if vault.paused_ops == 0 {
+            vault.paused_since_slot = Clock::get()?.slot;
+        }
+        vault.paused_ops |= ops;
 
-        // Only bets stay in the pot (lamports live in game_vault)
-        game.total_pot = bet_amount;
+        emit!(PausedEvent {
+            authority: ctx.accounts.authority.key(),
+            ops,
+            paused_ops: vault.paused_ops,
+        });
 
-        // snapshot current house fee
-        game.house_fee_bps = ctx.accounts.house_vault.house_fee_bps;
+        Ok(())
+    }
 
-        game.rounds_played = 0;
-        game.player1_wins = 0;
-        game.player2_wins = 0;
-        game.status = GameStatus::WaitingForPlayer2;
+    /// Resets the circuit breakers in `ops`.
+    ///
+    /// - Callable by the super-admin or a pauser.
+    pub fn unpause(ctx: Context<SetPause>, ops: u8) -> Result<()> {
+        require!(ops != 0 && ops & !PAUSABLE_OPS == 0, RpsError::InvalidPauseOps);
 
-        let clock = Clock::get()?;
-        game.created_slot = clock.slot;
+        let vault = &mut ctx.accounts.house_vault;
+        vault.paused_ops &= !ops;
+        if vault.paused_ops == 0 {
+            vault.paused_since_slot = 0;
+        }
 
-        game.commitments_p1 = [[0u8; 32]; MAX_ROUNDS];
-        game.commitments_p2 = [[0u8; 32]; MAX_ROUNDS];
-        game.committed_p1 = [false; MAX_ROUNDS];
-        game.committed_p2 = [false; MAX_ROUNDS];
-        game.moves_p1 = [0u8; MAX_ROUNDS];
-        game.moves_p2 = [0u8; MAX_ROUNDS];
-        game.revealed_p1 = [false; MAX_ROUNDS];
-        game.revealed_p2 = [false; MAX_ROUNDS];
-        game.commit_deadline_slots = [0u64; MAX_ROUNDS];
-        //game.reveal_deadline_slots = [0u64; MAX_ROUNDS];
-        game.round_resolved = [false; MAX_ROUNDS];
+        emit!(UnpausedEvent {
+            authority: ctx.accounts.authority.key(),
+            ops,
+            paused_ops: vault.paused_ops,
+        });
 
         Ok(())
     }
 
+    /// Nominates a new super-admin; takes effect once they call `accept_admin`.
+    ///
+    /// - Only the current `admin` may call this.
+    /// - Passing the default pubkey withdraws a pending nomination.
+    pub fn propose_admin(ctx: Context<ProposeAdmin>, new_admin: Pubkey) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        vault.pending_admin = new_admin;
 
-    /// Player 2 joins the game and deposits the same entry fee + bet.
-    pub fn join_game(ctx: Context<JoinGame>) -> Result<()> {
-        let game = &mut ctx.accounts.game;
+        emit!(AdminProposedEvent {
+            admin: vault.admin,
+            pending_admin: new_admin,
+        });
 
-        // Game must be open for Player2
-        require!(
-            game.status == GameStatus::WaitingForPlayer2,
-            RpsError::GameNotJoinable
-        );
-        require!(
-            game.player2 == Pubkey::default(),
-            RpsError::AlreadyHasPlayer2
-        );
+        Ok(())
+    }
 
-        // Canonical amounts from on-chain state (Player 2
-        // cannot choose their own bet/fee)
-        let bet_amount = game.bet_amount;
-        let entry_fee = game.entry_fee;
+    /// Completes a super-admin transfer started by `propose_admin`.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        let previous_admin = vault.accept_pending_admin(&ctx.accounts.new_admin.key())?;
 
-        // Same business rules as create_game
-        require!(bet_amount > 0, RpsError::InvalidBetAmount);
-        require!(entry_fee > 0, RpsError::InvalidEntryFee);
-        require!(bet_amount >= MIN_BET_LAMPORTS, RpsError::BetTooLow);
+        emit!(AdminTransferredEvent {
+            previous_admin,
+            new_admin: vault.admin,
+        });
 
-        // Player2 pays bet into game_vault
-        let cpi_ctx_bet = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.player2.to_account_info(),
-                to: ctx.accounts.game_vault.to_account_info(),
-            },
-        );
-        system_program::transfer(cpi_ctx_bet, bet_amount)?;
+        Ok(())
+    }
 
-        // Player2 pays entry fee into house_vault_sol
-        let cpi_ctx_fee = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.player2.to_account_info(),
-                to: ctx.accounts.house_vault_sol.to_account_info(),
-            },
-        );
-        system_program::transfer(cpi_ctx_fee, entry_fee)?;
+    /// Grants `role` to `member`, creating their role account on first grant.
+    ///
+    /// - Only the super-admin (`HouseVault.admin`) may call this.
+    pub fn grant_role(ctx: Context<GrantRole>, member: Pubkey, role: Role) -> Result<()> {
+        let assignment = &mut ctx.accounts.role_assignment;
+        assignment.bump = ctx.bumps.role_assignment;
+        assignment.member = member;
+        assignment.roles |= role.mask();
+
+        emit!(RoleGrantedEvent {
+            member,
+            role,
+            roles: assignment.roles,
+        });
 
-        // Update game state (only bets remain in the pot)
-        game.player2 = ctx.accounts.player2.key();
-        game.total_pot = game
-            .total_pot
-            .checked_add(bet_amount)
-            .ok_or(RpsError::MathOverflow)?;
+        Ok(())
+    }
 
-        // keep your existing next status, unless you want a more specific one
-        game.status = GameStatus::Active;
+    /// Revokes `role` from the member owning `role_assignment`.
+    ///
+    /// - Only the super-admin (`HouseVault.admin`) may call this.
+    pub fn revoke_role(ctx: Context<RevokeRole>, role: Role) -> Result<()> {
+        let assignment = &mut ctx.accounts.role_assignment;
+        assignment.roles &= !role.mask();
+
+        emit!(RoleRevokedEvent {
+            member: assignment.member,
+            role,
+            roles: assignment.roles,
+        });
 
         Ok(())
     }
 
-        /// Starts a round and opens the 5-second commit window on-chain.
+    /// Opens an admin proposal.
     ///
-    /// - Can be called by player1 / player2 or their session signers.
-    /// - Sets commit_deadline_slots[round] based on current slot.
-    /// - If already started or resolved, reverts.
-    pub fn start_round(
-        ctx: Context<StartRound>,
-        round_index: u8,
+    /// - Fee changes, withdrawals, payee changes and force refunds may only be
+    ///   proposed by a holder of the matching role; admin set changes by an admin
+    ///   set member.
+    /// - A proposer who is in the admin set has their approval counted immediately.
+    /// - The proposal lapses after `PROPOSAL_EXPIRY_SLOTS`.
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        action: ProposalAction,
     ) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-
-        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
-        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+        let house_vault = &mut ctx.accounts.house_vault;
+        let proposer_index = house_vault.admin_index(&ctx.accounts.proposer.key());
+
+        match action.required_role() {
+            Some(role) => {
+                let has_role = ctx
+                    .accounts
+                    .proposer_role
+                    .as_ref()
+                    .is_some_and(|assignment| assignment.has(role));
+                require!(has_role, RpsError::MissingRole);
+            }
+            None => require!(proposer_index.is_some(), RpsError::Unauthorized),
+        }
 
-        let idx = round_index as usize;
+        // Reject actions that could never execute
+        match action {
+            ProposalAction::SetHouseFee { new_fee_bps } => {
+                require!(new_fee_bps <= MAX_HOUSE_FEE_BPS, RpsError::InvalidHouseFee);
+            }
+            ProposalAction::SetRakeSchedule { schedule } => {
+                schedule.validate()?;
+            }
+            ProposalAction::WithdrawHouseFunds { amount, .. } => {
+                require!(amount > 0, RpsError::InvalidWithdrawAmount);
+            }
+            ProposalAction::AdminForceRefund { .. } => {}
+            ProposalAction::UpdateAdminSet {
+                admins,
+                admin_count,
+                threshold,
+            } => {
+                validate_admin_set(&admins, admin_count, threshold)?;
+            }
+            ProposalAction::SetPayees { split } => {
+                split.validate()?;
+            }
+        }
 
-        // Aynı round'u ikinci defa başlatma
-        require!(
-            game.commit_deadline_slots[idx] == 0,
-            RpsError::CommitWindowAlreadyStarted
-        );
-        require!(
-            !game.round_resolved[idx],
-            RpsError::RoundAlreadyResolved
-        );
+        let id = house_vault.proposal_count;
+        house_vault.proposal_count = id.checked_add(1).ok_or(RpsError::MathOverflow)?;
 
         let current_slot = Clock::get()?.slot;
-        let deadline = current_slot
-            .checked_add(COMMIT_PHASE_SLOTS)
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.bump = ctx.bumps.proposal;
+        proposal.id = id;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.action = action;
+        proposal.admin_set_version = house_vault.admin_set_version;
+        proposal.approvals = proposer_index.map_or(0, |index| 1u16 << index);
+        proposal.created_slot = current_slot;
+        proposal.expiry_slot = current_slot
+            .checked_add(PROPOSAL_EXPIRY_SLOTS)
             .ok_or(RpsError::MathOverflow)?;
 
-        game.commit_deadline_slots[idx] = deadline;
-
-        emit!(RoundStartEvent {
-            game_id: game.game_id,
-            round: round_index,
-            start_slot: current_slot,
-            commit_deadline_slot: deadline,
+        emit!(ProposalCreatedEvent {
+            proposal_id: id,
+            proposer: proposal.proposer,
+            action,
+            expiry_slot: proposal.expiry_slot,
         });
 
         Ok(())
     }
 
+    /// Adds the signing admin's approval to a pending proposal.
+    pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+        let house_vault = &ctx.accounts.house_vault;
+        let proposal = &mut ctx.accounts.proposal;
 
-    /// Commit move for a given round.
-    ///
-    /// - Only stores `commitment` (the 32-byte hash)
-    /// - Player identity is inferred from the signer account
-    pub fn commit_move(
-        ctx: Context<CommitMove>,
-        round_index: u8,
-        commitment: [u8; 32],
-    ) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-        let player = &ctx.accounts.player;
-
-        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
-        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
-
-        let idx = round_index as usize;
-        let current_slot = Clock::get()?.slot;
-
-        // koray - 28.11.2025 - Commit window MUST have been started by start_round.
-        let deadline = game.commit_deadline_slots[idx];
+        let admin_index = house_vault
+            .admin_index(&ctx.accounts.admin.key())
+            .ok_or(RpsError::Unauthorized)?;
         require!(
-            deadline != 0,
-            RpsError::CommitWindowNotStarted
+            proposal.admin_set_version == house_vault.admin_set_version,
+            RpsError::StaleProposal
         );
         require!(
-            current_slot <= deadline,
-            RpsError::CommitPhaseExpired
+            Clock::get()?.slot <= proposal.expiry_slot,
+            RpsError::ProposalExpired
         );
 
-        // ---- IMPORTANT: correctly classify who this signer is ----
-        let pk = player.key();
-        let is_p1 = pk == game.player1 || pk == game.session_p1;
-        let is_p2 = pk == game.player2 || pk == game.session_p2;
-        require!(is_p1 || is_p2, RpsError::NotAPlayer);
+        let bit = 1u16 << admin_index;
+        require!(proposal.approvals & bit == 0, RpsError::AlreadyApproved);
+        proposal.approvals |= bit;
 
-        if is_p1 {
-            require!(!game.committed_p1[idx], RpsError::AlreadyCommitted);
-            game.commitments_p1[idx] = commitment;
-            game.committed_p1[idx] = true;
-        } else {
-            require!(!game.committed_p2[idx], RpsError::AlreadyCommitted);
-            game.commitments_p2[idx] = commitment;
-            game.committed_p2[idx] = true;
-        }
-
-        // When both commits are in, start the reveal window and notify clients.
-        let both_committed = game.committed_p1[idx] && game.committed_p2[idx];
-
-        emit!(RoundPhaseEvent {
-            game_id: game.game_id,
-            round: round_index,
-            current_slot,
-            commit_deadline_slot: game.commit_deadline_slots[idx],
-            reveal_deadline_slot: 0, // CHANGED: no reveal deadline on-chain
-            both_committed,
-        });
+        emit!(ProposalApprovedEvent {
+            proposal_id: proposal.id,
+            admin: ctx.accounts.admin.key(),
+            approvals: proposal.approvals.count_ones() as u8,
+            threshold: house_vault.threshold,
+        });
 
         Ok(())
     }
 
-
-    /// Reveal move for a given round.
+    /// Withdraws a proposal (pending, stale or expired) and returns its rent.
     ///
-    /// - Verifies hash(move || nonce || game_id || round_index || player_pubkey)
-    ///   matches previously stored commitment.
-    /// - If both players revealed, computes round winner and possibly finishes game.
-    pub fn reveal_move(
-        ctx: Context<RevealMove>,
-        round_index: u8,
-        move_value: u8,
-        nonce: [u8; 32],
-    ) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-        let player = &ctx.accounts.player;
-    
-        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
-        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
-        require!(move_value <= 2, RpsError::InvalidMove);
-    
-        let idx = round_index as usize;
-    
-        // koray - 28.11.2025 CHANGED: no reveal time limit, but enforce both commits and not resolved
-        require!(
-            game.committed_p1[idx] && game.committed_p2[idx],
-            RpsError::BothMustCommitFirst
-        );
-        require!(
-            !game.round_resolved[idx],
-            RpsError::RoundAlreadyResolved
-        );
-    
-        let pk = player.key();
-        let is_p1 = pk == game.player1 || pk == game.session_p1;
-        let is_p2 = pk == game.player2 || pk == game.session_p2;
-        require!(is_p1 || is_p2, RpsError::NotAPlayer);
-    
-        // recompute commitment hash
-        let commitment_pubkey = if is_p1 { game.player1 } else { game.player2 };
-    
-        let mut hasher = Sha256::new();
-        hasher.update(&[move_value]);
-        hasher.update(&nonce);
-        hasher.update(&game.game_id);
-        hasher.update(&[round_index]);
-        hasher.update(commitment_pubkey.as_ref());
-        let hash = hasher.finalize();
-        let mut hash_bytes = [0u8; 32];
-        hash_bytes.copy_from_slice(&hash[..]);
-    
-        if is_p1 {
-            require!(game.committed_p1[idx], RpsError::NotCommittedYet);
-            require!(!game.revealed_p1[idx], RpsError::AlreadyRevealed);
-            require!(
-                game.commitments_p1[idx] == hash_bytes,
-                RpsError::CommitmentMismatch
-            );
-            game.moves_p1[idx] = move_value;
-            game.revealed_p1[idx] = true;
-        } else {
-            require!(game.committed_p2[idx], RpsError::NotCommittedYet);
-            require!(!game.revealed_p2[idx], RpsError::AlreadyRevealed);
-            require!(
-                game.commitments_p2[idx] == hash_bytes,
-                RpsError::CommitmentMismatch
-            );
-            game.moves_p2[idx] = move_value;
-            game.revealed_p2[idx] = true;
-        }
-    
-        // resolution logic
-        if game.revealed_p1[idx] && game.revealed_p2[idx] {
-            let m1 = game.moves_p1[idx];
-            let m2 = game.moves_p2[idx];
-            let round_result = round_winner(m1, m2);
-    
-            match round_result {
-                RoundResult::Player1Win => {
-                    game.player1_wins = game
-                        .player1_wins
-                        .checked_add(1)
-                        .ok_or(RpsError::MathOverflow)?;
-                    game.rounds_played = game
-                        .rounds_played
-                        .checked_add(1)
-                        .ok_or(RpsError::MathOverflow)?;
-                }
-                RoundResult::Player2Win => {
-                    game.player2_wins = game
-                        .player2_wins
-                        .checked_add(1)
-                        .ok_or(RpsError::MathOverflow)?;
-                    game.rounds_played = game
-                        .rounds_played
-                        .checked_add(1)
-                        .ok_or(RpsError::MathOverflow)?;
-                }
-                RoundResult::Draw => {
-                    // Draw da bir "round" sayılıyor ama kimse win almıyor
-                    game.rounds_played = game
-                        .rounds_played
-                        .checked_add(1)
-                        .ok_or(RpsError::MathOverflow)?;
-                    msg!("Round {} ended in a TIE", round_index);
-                }
-            }
-    
-            game.round_resolved[idx] = true;
-    
-            if game.player1_wins >= 3
-                || game.player2_wins >= 3
-                || game.rounds_played >= MAX_ROUNDS as u8
-            {
-                game.status = GameStatus::Finished;
-            }
-    
-            emit!(RoundResultEvent {
-                game_id: game.game_id,
-                round: round_index,
-                player1_wins: game.player1_wins,
-                player2_wins: game.player2_wins,
-                rounds_played: game.rounds_played,
-                status: game.status,
-            });
-        }
-    
+    /// - Only the original proposer may cancel.
+    pub fn cancel_proposal(ctx: Context<CancelProposal>) -> Result<()> {
+        emit!(ProposalCancelledEvent {
+            proposal_id: ctx.accounts.proposal.id,
+            proposer: ctx.accounts.proposer.key(),
+        });
+
+        // Anchor will close `proposal` and send its rent to the proposer
         Ok(())
     }
-    
 
-        /// Resolves a round by timeout after the commit window has expired.
+    /// Executes an approved `SetHouseFee` proposal by scheduling the new fee.
     ///
-    /// - Can be called by anyone (mediator, any user).
-    /// - Only allowed if:
-    ///   * Game is Active
-    ///   * Commit window was started for that round
-    ///   * Current slot > commit_deadline_slots[round]
-    ///   * Round not already resolved
-    ///   * NOT both players committed (if both committed, must use reveal_move)
-    /// - Outcome rules:
-    ///   * Only P1 committed  -> P1 wins the round
-    ///   * Only P2 committed  -> P2 wins the round
-    ///   * None committed     -> Draw
-    pub fn resolve_commit_timeout(
-        ctx: Context<ResolveCommitTimeout>,
-        round_index: u8,
-    ) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-    
-        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
-        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
-    
-        let idx = round_index as usize;
-        let current_slot = Clock::get()?.slot;
-    
-        // Commit penceresi başlatılmış olmalı
-        require!(
-            game.commit_deadline_slots[idx] != 0,
-            RpsError::CommitWindowNotStarted
-        );
-        // Ve commit süresi bitmiş olmalı
-        require!(
-            current_slot > game.commit_deadline_slots[idx],
-            RpsError::CommitPhaseNotExpired
-        );
-        // Aynı round'u ikinci kere resolve etmeyelim
-        require!(
-            !game.round_resolved[idx],
-            RpsError::RoundAlreadyResolved
-        );
-    
-        let c1 = game.committed_p1[idx];
-        let c2 = game.committed_p2[idx];
-    
-        // Eğer iki taraf da commit ettiyse time-out resolve yasak,
-        // mutlaka reveal ile devam etmelisin.
-        require!(!(c1 && c2), RpsError::BothCommittedNoTimeout);
-    
-        let result = if c1 && !c2 {
-            RoundResult::Player1Win
-        } else if !c1 && c2 {
-            RoundResult::Player2Win
-        } else {
-            RoundResult::Draw
+    /// - Only a fee manager may execute.
+    /// - The fee becomes active `HOUSE_FEE_TIMELOCK_SLOTS` later; until then it is
+    ///   visible as `pending_house_fee_bps` and can be cancelled.
+    /// - Replaces any fee that is still pending.
+    pub fn set_house_fee(ctx: Context<SetHouseFee>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        let proposal = &ctx.accounts.proposal;
+
+        require_approved(vault, proposal)?;
+        let new_fee_bps = match proposal.action {
+            ProposalAction::SetHouseFee { new_fee_bps } => new_fee_bps,
+            _ => return err!(RpsError::ProposalActionMismatch),
         };
-    
-        match result {
-            RoundResult::Player1Win => {
-                game.player1_wins = game
-                    .player1_wins
-                    .checked_add(1)
-                    .ok_or(RpsError::MathOverflow)?;
-                game.rounds_played = game
-                    .rounds_played
-                    .checked_add(1)
-                    .ok_or(RpsError::MathOverflow)?;
-            }
-            RoundResult::Player2Win => {
-                game.player2_wins = game
-                    .player2_wins
-                    .checked_add(1)
-                    .ok_or(RpsError::MathOverflow)?;
-                game.rounds_played = game
-                    .rounds_played
-                    .checked_add(1)
-                    .ok_or(RpsError::MathOverflow)?;
-            }
-            RoundResult::Draw => {
-                // Kimse commit etmediyse de bu round yanmış kabul ediyoruz
-                game.rounds_played = game
-                    .rounds_played
-                    .checked_add(1)
-                    .ok_or(RpsError::MathOverflow)?;
-                msg!(
-                    "Timeout round {} ended in a TIE (no commits) - round consumed",
-                    round_index
-                );
-            }
-        }
-    
-        game.round_resolved[idx] = true;
-    
-        if game.player1_wins >= 3
-            || game.player2_wins >= 3
-            || game.rounds_played >= MAX_ROUNDS as u8
-        {
-            game.status = GameStatus::Finished;
-        }
-    
-        emit!(RoundResultEvent {
-            game_id: game.game_id,
-            round: round_index,
-            player1_wins: game.player1_wins,
-            player2_wins: game.player2_wins,
-            rounds_played: game.rounds_played,
-            status: game.status,
+        require!(new_fee_bps <= MAX_HOUSE_FEE_BPS, RpsError::InvalidHouseFee);
+
+        let current_slot = Clock::get()?.slot;
+        vault.apply_pending_house_fee(current_slot);
+
+        let activation_slot = current_slot
+            .checked_add(HOUSE_FEE_TIMELOCK_SLOTS)
+            .ok_or(RpsError::MathOverflow)?;
+        vault.pending_house_fee_bps = new_fee_bps;
+        vault.pending_fee_activation_slot = activation_slot;
+
+        emit!(HouseFeeScheduledEvent {
+            current_fee_bps: vault.house_fee_bps,
+            pending_fee_bps: new_fee_bps,
+            activation_slot,
         });
-    
+        emit!(ProposalExecutedEvent {
+            proposal_id: proposal.id,
+            executor: ctx.accounts.fee_manager.key(),
+        });
+
+        // Anchor will close `proposal` and send its rent to the proposer
         Ok(())
     }
-    
 
-    /// Forfeit game - ends the game immediately and declares a winner.
+    /// Executes an approved `SetRakeSchedule` proposal by scheduling the new tiers.
     ///
-    /// - Called when a player disconnects, times out, or abandons the game.
-    /// - Can be called by anyone (mediator, player, or any user).
-    /// - The caller must specify who forfeited (loser).
-    /// - The other player wins by default (3 wins credited).
-    /// - Game status is set to Finished, ready for settlement.
-    pub fn forfeit_game(
-        ctx: Context<ForfeitGame>,
-        loser_is_player1: bool,
-    ) -> Result<()> {
-        let game = &mut ctx.accounts.game;
+    /// - Only a fee manager may execute.
+    /// - Same timelock as `set_house_fee`; replaces any schedule still pending.
+    pub fn set_rake_schedule(ctx: Context<SetHouseFee>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        let proposal = &ctx.accounts.proposal;
 
-        // Game must be Active
-        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+        require_approved(vault, proposal)?;
+        let schedule = match proposal.action {
+            ProposalAction::SetRakeSchedule { schedule } => schedule,
+            _ => return err!(RpsError::ProposalActionMismatch),
+        };
+        schedule.validate()?;
 
-        // Set the winner
-        if loser_is_player1 {
-            // PLAYER1 forfeited/disconnected -> PLAYER2 wins
-            game.player2_wins = 3;
-            msg!("PLAYER1 forfeited. PLAYER2 wins!");
-        } else {
-            // PLAYER2 forfeited/disconnected -> PLAYER1 wins
-            game.player1_wins = 3;
-            msg!("PLAYER2 forfeited. PLAYER1 wins!");
-        }
+        let current_slot = Clock::get()?.slot;
+        vault.apply_pending_rake_schedule(current_slot);
 
-        // Mark game as finished
-        game.status = GameStatus::Finished;
+        let activation_slot = current_slot
+            .checked_add(HOUSE_FEE_TIMELOCK_SLOTS)
+            .ok_or(RpsError::MathOverflow)?;
+        vault.pending_rake_schedule = schedule;
+        vault.pending_rake_activation_slot = activation_slot;
 
-        emit!(GameForfeitEvent {
-            game_id: game.game_id,
-            loser: if loser_is_player1 { game.player1 } else { game.player2 },
-            winner: if loser_is_player1 { game.player2 } else { game.player1 },
+        emit!(RakeScheduleScheduledEvent {
+            schedule,
+            activation_slot,
+        });
+        emit!(ProposalExecutedEvent {
+            proposal_id: proposal.id,
+            executor: ctx.accounts.fee_manager.key(),
         });
 
+        // Anchor will close `proposal` and send its rent to the proposer
         Ok(())
     }
 
-    /// Cancel game - refunds both players their bets.
+    /// Drops a scheduled rake schedule change before it activates.
     ///
-    /// - Called when there's an error (blockchain timeout, commit phase expired, etc.)
-    /// - Can be called by anyone (mediator, player, or any user).
-    /// - Both players get their bets refunded (no house fee).
-    /// - Game status is set to Cancelled.
-    pub fn cancel_game(ctx: Context<CancelGame>) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-
-        // Game must be Active (not already Finished/Settled/Cancelled)
-        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+    /// - Only a fee manager may call this.
+    pub fn cancel_pending_rake_schedule(ctx: Context<ManageFees>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
 
-        // Mark game as Cancelled
-        game.status = GameStatus::Cancelled;
+        require!(vault.pending_rake_activation_slot != 0, RpsError::NoPendingHouseFee);
+        require!(
+            Clock::get()?.slot < vault.pending_rake_activation_slot,
+            RpsError::HouseFeeAlreadyActive
+        );
 
-        let total_pot = game.total_pot;
-        let bet_amount = game.bet_amount;
+        vault.pending_rake_schedule = RakeSchedule::default();
+        vault.pending_rake_activation_slot = 0;
 
-        // Calculate refunds - each player gets their bet back
-        // If only player1 has deposited, they get full refund
-        // If both players deposited, each gets their bet back
-        let player1_refund = bet_amount;
-        let player2_refund = if total_pot >= bet_amount * 2 {
-            bet_amount
-        } else if total_pot > bet_amount {
-            total_pot - bet_amount
-        } else {
-            0
-        };
+        emit!(RakeScheduleCancelledEvent {
+            current_schedule: vault.rake_schedule,
+        });
 
-        msg!("Cancelling game. Refunding player1: {} lamports, player2: {} lamports",
-             player1_refund, player2_refund);
+        Ok(())
+    }
 
-        // Transfer refunds from game vault
-        let game_vault = &ctx.accounts.game_vault;
-        let player1 = &ctx.accounts.player1;
-        let player2 = &ctx.accounts.player2;
+    /// Sets the share of the rake credited to players' referrers.
+    ///
+    /// - Only a fee manager may call this; at most `MAX_REFERRAL_SHARE_BPS`.
+    /// - Games snapshot the share at creation.
+    pub fn set_referral_share(ctx: Context<ManageFees>, share_bps: u16) -> Result<()> {
+        require!(share_bps <= MAX_REFERRAL_SHARE_BPS, RpsError::InvalidReferralShare);
 
-        // Use game_id as seeds for the vault PDA (must match "game_vault" seed used in create_game)
-        let game_id = game.game_id;
-        let vault_seeds: &[&[u8]] = &[
-            b"game_vault",
-            &game_id,
-            &[ctx.bumps.game_vault],
-        ];
-        let vault_signer = &[vault_seeds];
+        ctx.accounts.house_vault.referral_share_bps = share_bps;
+        Ok(())
+    }
 
-        // Refund player1
-        if player1_refund > 0 {
-            let transfer_ix_p1 = anchor_lang::solana_program::system_instruction::transfer(
-                &game_vault.key(),
-                &player1.key(),
-                player1_refund,
-            );
-            anchor_lang::solana_program::program::invoke_signed(
-                &transfer_ix_p1,
-                &[
-                    game_vault.to_account_info(),
-                    player1.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-                vault_signer,
-            )?;
-        }
+    /// Sets how much of the entry fee cancelled games give back, per cancellation reason.
+    ///
+    /// - Only a fee manager may call this.
+    /// - Games snapshot the policy at creation.
+    pub fn set_fee_refund_policy(
+        ctx: Context<ManageFees>,
+        policy: FeeRefundPolicy,
+    ) -> Result<()> {
+        policy.validate()?;
 
-        // Refund player2
-        if player2_refund > 0 {
-            let transfer_ix_p2 = anchor_lang::solana_program::system_instruction::transfer(
-                &game_vault.key(),
-                &player2.key(),
-                player2_refund,
-            );
-            anchor_lang::solana_program::program::invoke_signed(
-                &transfer_ix_p2,
-                &[
-                    game_vault.to_account_info(),
-                    player2.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-                vault_signer,
-            )?;
-        }
+        ctx.accounts.house_vault.fee_refund_policy = policy;
+        Ok(())
+    }
 
-        emit!(GameCancelledEvent {
-            game_id: game.game_id,
-            player1: game.player1,
-            player2: game.player2,
-            player1_refund,
-            player2_refund,
-        });
+    /// Caps the fee integrators (third-party frontends) may add to their games.
+    ///
+    /// - Only a fee manager may call this; at most `MAX_INTEGRATOR_FEE_BPS`.
+    pub fn set_max_integrator_fee(ctx: Context<ManageFees>, max_fee_bps: u16) -> Result<()> {
+        require!(max_fee_bps <= MAX_INTEGRATOR_FEE_BPS, RpsError::InvalidIntegratorFee);
 
+        ctx.accounts.house_vault.max_integrator_fee_bps = max_fee_bps;
         Ok(())
     }
 
-
-    /// Settles the game: pays the winner, house fee, or splits pot.
+    /// Drops a scheduled house fee change before it activates.
     ///
-    /// - Can be called by anyone once `status == Finished`.
-    /// - Transfers from `game_vault` account to `winner` & `house_vault_sol`.
-    /// - Closes `game` account and returns remaining rent to `player1`.
-    pub fn settle_game(ctx: Context<SettleGame>) -> Result<()> {
-        let game = &mut ctx.accounts.game;
+    /// - Only a fee manager may call this.
+    pub fn cancel_pending_house_fee(ctx: Context<ManageFees>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
 
+        require!(vault.pending_fee_activation_slot != 0, RpsError::NoPendingHouseFee);
         require!(
-            game.status == GameStatus::Finished,
-            RpsError::GameNotFinished
+            Clock::get()?.slot < vault.pending_fee_activation_slot,
+            RpsError::HouseFeeAlreadyActive
         );
-        game.status = GameStatus::Settled;
 
-        let total_pot = game.total_pot;
-        require!(total_pot > 0, RpsError::InvalidBetAmount);
+        let cancelled_fee_bps = vault.pending_house_fee_bps;
+        vault.pending_house_fee_bps = 0;
+        vault.pending_fee_activation_slot = 0;
 
-        let player1 = &ctx.accounts.player1;
-        let player2 = &ctx.accounts.player2;
+        emit!(HouseFeeCancelledEvent {
+            cancelled_fee_bps,
+            current_fee_bps: vault.house_fee_bps,
+        });
 
-        // Winner determination
-        let winner: Option<Pubkey> = if game.player1_wins > game.player2_wins {
-            Some(game.player1)
-        } else if game.player2_wins > game.player1_wins {
-            Some(game.player2)
-        } else {
-            None
-        };
+        Ok(())
+    }
 
-        let (payout_p1, payout_p2, house_fee) = if let Some(winner_pk) = winner {
-            let house_fee = total_pot
-                .checked_mul(game.house_fee_bps as u64)
-                .ok_or(RpsError::MathOverflow)?
-                .checked_div(BPS_DENOMINATOR)
-                .ok_or(RpsError::MathOverflow)?;
-
-            let winner_amount = total_pot
-                .checked_sub(house_fee)
-                .ok_or(RpsError::MathOverflow)?;
-
-            if winner_pk == game.player1 {
-                (winner_amount, 0, house_fee)
-            } else {
-                (0, winner_amount, house_fee)
-            }
-        } else {
-            // draw: split pot, no rake
-            let half = total_pot
-                .checked_div(2)
-                .ok_or(RpsError::MathOverflow)?;
-            let remainder = total_pot
-                .checked_sub(half.checked_mul(2).ok_or(RpsError::MathOverflow)?)
-                .ok_or(RpsError::MathOverflow)?;
-            (half + remainder, half, 0)
+    /// Executes an approved `UpdateAdminSet` proposal.
+    ///
+    /// - Bumps `admin_set_version`, so every other outstanding proposal goes stale.
+    pub fn update_admin_set(ctx: Context<UpdateAdminSet>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        let proposal = &ctx.accounts.proposal;
+
+        require_approved(vault, proposal)?;
+        let (admins, admin_count, threshold) = match proposal.action {
+            ProposalAction::UpdateAdminSet {
+                admins,
+                admin_count,
+                threshold,
+            } => (admins, admin_count, threshold),
+            _ => return err!(RpsError::ProposalActionMismatch),
         };
+        validate_admin_set(&admins, admin_count, threshold)?;
+
+        vault.admins = admins;
+        vault.admin_count = admin_count;
+        vault.threshold = threshold;
+        vault.admin_set_version = vault
+            .admin_set_version
+            .checked_add(1)
+            .ok_or(RpsError::MathOverflow)?;
 
-        // seeds for the system-owned game_vault PDA
-        let game_vault_bump = ctx.bumps.game_vault;
-        let seeds: &[&[u8]] = &[
-            b"game_vault",
-            game.game_id.as_ref(),
-            &[game_vault_bump],
-        ];
-        let signer_seeds: &[&[&[u8]]] = &[seeds];
-
-        let game_vault_ai = ctx.accounts.game_vault.to_account_info();
-        let system_program = &ctx.accounts.system_program;
-
-        // payouts from game_vault
-        transfer_with_signer(
-            payout_p1,
-            &game_vault_ai,
-            &player1,
-            system_program,
-            signer_seeds,
-        )?;
-        transfer_with_signer(
-            payout_p2,
-            &game_vault_ai,
-            &player2,
-            system_program,
-            signer_seeds,
-        )?;
-        transfer_with_signer(
-            house_fee,
-            &game_vault_ai,
-            &ctx.accounts.house_vault_sol.to_account_info(),
-            system_program,
-            signer_seeds,
-        )?;
+        emit!(ProposalExecutedEvent {
+            proposal_id: proposal.id,
+            executor: ctx.accounts.admin.key(),
+        });
 
-        // Anchor will close `game` and send its rent to player1 due to `close = player1`
+        // Anchor will close `proposal` and send its rent to the proposer
         Ok(())
     }
 
-    /// Withdraws SOL from the global house vault PDA to the admin wallet.
+    /// Player 1 creates the game and deposits entry fee + bet.
     ///
-    /// - Only the stored `admin` in `HouseVault` is allowed to call this.
-    /// - Signs with the `house_vault_sol` PDA seeds.
-    pub fn withdraw_house_funds(
-        ctx: Context<WithdrawHouseFunds>,
-        amount: u64,
+    /// - `game_id` is a 32-byte identifier (e.g. uuid bytes or hash of it)
+    /// - `bet_amount` is per-player bet (lamports)
+    /// - `entry_fee` is per-player fee (lamports), only refunded on cancellation as
+    ///   far as the house fee refund policy says
+    /// - `integrator_fee_bps` is taken from decided pots on top of the house rake
+    ///   and credited to the optional `integrator_rewards` account (0 without one)
+    /// - With `player1_balance` passed, bet and fee come out of the player's balance
+    ///   account instead of the wallet, and settlement pays back into it.
+    pub fn create_game(
+        ctx: Context<CreateGame>,
+        game_id: [u8; 32],
+        bet_amount: u64,
+        entry_fee: u64,
+        integrator_fee_bps: u16,
     ) -> Result<()> {
-        // Admin auth is enforced by account constraint (address = house_vault.admin)
-
-        let bump = ctx.bumps.house_vault_sol;
-        let seeds: &[&[u8]] = &[
-            b"house_vault_sol",
-            &[bump],
-        ];
-        let signer_seeds: &[&[&[u8]]] = &[seeds];
-
-        transfer_with_signer(
-            amount,
-            &ctx.accounts.house_vault_sol.to_account_info(),
-            &ctx.accounts.admin.to_account_info(),
-            &ctx.accounts.system_program,
-            signer_seeds,
-        )
-    }
+        require_not_paused(&ctx.accounts.house_vault, PAUSE_CREATE_GAME)?;
 
-    /// Authorize a delegated session signer for this game.
-    ///
-    /// - The `player` must be either player1 or player2.
-    /// - `session_pubkey` will be allowed to call commit_move / reveal_move for this game.
-    pub fn authorize_session_signer(
-        ctx: Context<AuthorizeSessionSigner>,
-        session_pubkey: Pubkey,
-    ) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-        let player_key = ctx.accounts.player.key();
+        // basic validation
+        require!(bet_amount > 0, RpsError::InvalidBetAmount);
+        require!(entry_fee > 0, RpsError::InvalidEntryFee);
+        require!(
+            integrator_fee_bps <= ctx.accounts.house_vault.max_integrator_fee_bps,
+            RpsError::InvalidIntegratorFee
+        );
+        let integrator = ctx
+            .accounts
+            .integrator_rewards
+            .as_ref()
+            .map_or(Pubkey::default(), |rewards| rewards.integrator);
+        require!(
+            integrator != Pubkey::default() || integrator_fee_bps == 0,
+            RpsError::InvalidIntegratorFee
+        );
 
-        // Optional: only allow while game is not finished
+        // enforce min bet = 0.1 SOL
         require!(
-            game.status == GameStatus::WaitingForPlayer2 || game.status == GameStatus::Active,
-            RpsError::InvalidGameState
+            bet_amount >= MIN_BET_LAMPORTS,
+            RpsError::BetTooLow
         );
 
-        if player_key == game.player1 {
-            game.session_p1 = session_pubkey;
-        } else if player_key == game.player2 {
-            game.session_p2 = session_pubkey;
+        let staked_from_balance = ctx.accounts.player1_balance.is_some();
+        if let Some(balance) = ctx.accounts.player1_balance.as_mut() {
+            stake_from_balance(
+                balance,
+                bet_amount,
+                entry_fee,
+                &ctx.accounts.game_vault,
+                &ctx.accounts.fee_shard.to_account_info(),
+            )?;
         } else {
-            return Err(RpsError::NotAPlayer.into());
+            // Player1 pays bet_amount into the per-game vault PDA
+            let cpi_ctx_bet = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player1.to_account_info(),
+                    to: ctx.accounts.game_vault.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_ctx_bet, bet_amount)?;
+
+            // Player1 pays entry_fee into this game's house fee shard
+            let cpi_ctx_fee = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player1.to_account_info(),
+                    to: ctx.accounts.fee_shard.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_ctx_fee, entry_fee)?;
         }
+        ctx.accounts.fee_shard.stats.record_game_created()?;
+        ctx.accounts.fee_shard.stats.record_deposit(bet_amount, entry_fee)?;
 
-        Ok(())
-    }
+        // Init game state
+        let game = &mut ctx.accounts.game;
 
-    /// Admin force refund - emergency function to refund stuck games.
-    ///
-    /// - Only callable by house_vault admin.
-    /// - Works on any game status (WaitingForPlayer2, Active, Cancelled, etc.)
-    /// - Refunds all vault funds to player1 (since player2 may not exist on-chain).
-    /// - Closes game account and returns rent to player1.
-    /// - Use this when normal cancel_game fails (e.g., player2 is System Program).
-    pub fn admin_force_refund(ctx: Context<AdminForceRefund>) -> Result<()> {
-        let game = &ctx.accounts.game;
+        game.bump = ctx.bumps.game;
+        game.game_id = game_id;
 
-        // Admin auth is enforced by account constraint
-        msg!("Admin force refund for game: {:?}", game.game_id);
-        msg!("Current status: {:?}", game.status as u8);
+        game.player1 = ctx.accounts.player1.key();
+        game.player2 = Pubkey::default();
 
-        // Get vault balance
-        let vault_balance = ctx.accounts.game_vault.lamports();
-        msg!("Vault balance: {} lamports", vault_balance);
-
-        // Transfer all vault funds to player1
-        if vault_balance > 0 {
-            let bump = ctx.bumps.game_vault;
-            let seeds: &[&[u8]] = &[
-                b"game_vault",
-                game.game_id.as_ref(),
-                &[bump],
-            ];
-            let signer_seeds: &[&[&[u8]]] = &[seeds];
-
-            transfer_with_signer(
-                vault_balance,
-                &ctx.accounts.game_vault.to_account_info(),
-                &ctx.accounts.player1.to_account_info(),
-                &ctx.accounts.system_program,
-                signer_seeds,
-            )?;
+        game.house_vault = ctx.accounts.house_vault.key();
 
-            msg!("Refunded {} lamports to player1", vault_balance);
-        }
+        game.session_p1 = Pubkey::default();
+        game.session_p2 = Pubkey::default();
+        game.session_expiry_p1 = 0;
+        game.session_expiry_p2 = 0;
+        game.session_perms_p1 = 0;
+        game.session_perms_p2 = 0;
 
-        emit!(AdminForceRefundEvent {
-            game_id: game.game_id,
-            player1: game.player1,
-            refund_amount: vault_balance,
-            admin: ctx.accounts.admin.key(),
-        });
+        // store bet & entry fee in state
+        game.bet_amount = bet_amount;
+        game.entry_fee = entry_fee;
+
+        // Only bets stay in the pot (lamports live in game_vault)
+        game.total_pot = bet_amount;
+        game.deposit_p1 = bet_amount;
+        game.deposit_p2 = 0;
+        game.fee_deposit_p1 = entry_fee;
+        game.fee_deposit_p2 = 0;
+        game.staked_from_balance_p1 = staked_from_balance;
+        game.staked_from_balance_p2 = false;
+
+        // snapshot the rake for this bet size and the escape hatch delay
+        let house_vault = &ctx.accounts.house_vault;
+        let current_slot = Clock::get()?.slot;
+        let rake_schedule = house_vault.effective_rake_schedule(current_slot);
+        game.house_fee_bps =
+            rake_schedule.fee_bps_for(bet_amount, house_vault.effective_house_fee_bps(current_slot));
+        game.min_house_fee = rake_schedule.min_fee;
+        game.max_house_fee = rake_schedule.max_fee;
+        game.referral_share_bps = house_vault.referral_share_bps;
+        game.referrer_p1 = registered_referrer(&ctx.accounts.player1_referral)?;
+        game.referrer_p2 = Pubkey::default();
+        game.integrator = integrator;
+        game.integrator_fee_bps = integrator_fee_bps;
+        game.emergency_exit_delay_slots = ctx.accounts.house_vault.emergency_exit_delay_slots;
+        game.fee_refund_policy = ctx.accounts.house_vault.fee_refund_policy;
+
+        game.rounds_played = 0;
+        game.player1_wins = 0;
+        game.player2_wins = 0;
+        game.status = GameStatus::WaitingForPlayer2;
+
+        let clock = Clock::get()?;
+        game.created_slot = clock.slot;
+        game.last_action_slot = clock.slot;
+
+        game.commitments_p1 = [[0u8; 32]; MAX_ROUNDS];
+        game.commitments_p2 = [[0u8; 32]; MAX_ROUNDS];
+        game.committed_p1 = [false; MAX_ROUNDS];
+        game.committed_p2 = [false; MAX_ROUNDS];
+        game.moves_p1 = [0u8; MAX_ROUNDS];
+        game.moves_p2 = [0u8; MAX_ROUNDS];
+        game.revealed_p1 = [false; MAX_ROUNDS];
+        game.revealed_p2 = [false; MAX_ROUNDS];
+        game.commit_deadline_slots = [0u64; MAX_ROUNDS];
+        game.reveal_deadline_slots = [0u64; MAX_ROUNDS];
+        game.round_resolved = [false; MAX_ROUNDS];
+        game.channel_seq = 0;
+        game.channel_challenge_deadline = 0;
+        game.match_root_p1 = [0u8; 32];
+        game.match_root_p2 = [0u8; 32];
 
-        // Anchor will close game account and send rent to player1
+        require_escrow_healthy(game, &ctx.accounts.game_vault)?;
         Ok(())
     }
 
-    /// Allows player1 to cancel a game that never started (player2 never joined)
-    /// after TIMEOUT_SLOTS have passed since creation.
-    ///
-    /// - Refunds player1's bet from game_vault (entry fee stays with house).
-    pub fn cancel_game_if_timed_out(ctx: Context<CancelGameIfTimedOut>) -> Result<()> {
-        let game = &ctx.accounts.game;
 
+    /// Player 2 joins the game and deposits the same entry fee + bet.
+    pub fn join_game(ctx: Context<JoinGame>) -> Result<()> {
+        require_not_paused(&ctx.accounts.house_vault, PAUSE_JOIN_GAME)?;
+
+        let game = &mut ctx.accounts.game;
+
+        // Game must be open for Player2
         require!(
             game.status == GameStatus::WaitingForPlayer2,
-            RpsError::GameNotCancellable
+            RpsError::GameNotJoinable
         );
-        require_keys_eq!(ctx.accounts.player1.key(), game.player1, RpsError::NotAPlayer);
-
-        let current_slot = Clock::get()?.slot;
         require!(
-            current_slot >= game.created_slot + TIMEOUT_SLOTS,
-            RpsError::NotTimedOut
+            game.player2 == Pubkey::default(),
+            RpsError::AlreadyHasPlayer2
+        );
+        require_keys_neq!(
+            ctx.accounts.player2.key(),
+            game.player1,
+            RpsError::CannotJoinOwnGame
         );
 
-        // refund pot to player1 from game_vault
-        let amount = game.total_pot;
-        if amount > 0 {
-            let bump = ctx.bumps.game_vault;
-            let seeds: &[&[u8]] = &[
-                b"game_vault",
-                game.game_id.as_ref(),
-                &[bump],
-            ];
-            let signer_seeds: &[&[&[u8]]] = &[seeds];
+        // Canonical amounts from on-chain state (Player 2
+        // cannot choose their own bet/fee)
+        let bet_amount = game.bet_amount;
+        let entry_fee = game.entry_fee;
 
-            transfer_with_signer(
-                amount,
-                &ctx.accounts.game_vault.to_account_info(),
-                &ctx.accounts.player1.to_account_info(),
-                &ctx.accounts.system_program,
-                signer_seeds,
+        // Same business rules as create_game
+        require!(bet_amount > 0, RpsError::InvalidBetAmount);
+        require!(entry_fee > 0, RpsError::InvalidEntryFee);
+        require!(bet_amount >= MIN_BET_LAMPORTS, RpsError::BetTooLow);
+
+        let staked_from_balance = ctx.accounts.player2_balance.is_some();
+        if let Some(balance) = ctx.accounts.player2_balance.as_mut() {
+            stake_from_balance(
+                balance,
+                bet_amount,
+                entry_fee,
+                &ctx.accounts.game_vault,
+                &ctx.accounts.fee_shard.to_account_info(),
             )?;
+        } else {
+            // Player2 pays bet into game_vault
+            let cpi_ctx_bet = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player2.to_account_info(),
+                    to: ctx.accounts.game_vault.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_ctx_bet, bet_amount)?;
+
+            // Player2 pays entry fee into the same house fee shard
+            let cpi_ctx_fee = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player2.to_account_info(),
+                    to: ctx.accounts.fee_shard.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_ctx_fee, entry_fee)?;
         }
+        ctx.accounts.fee_shard.stats.record_deposit(bet_amount, entry_fee)?;
 
-        // Anchor will close game and send its rent to player1
-        Ok(())
-    }
-}
+        // Update game state (only bets remain in the pot)
+        game.player2 = ctx.accounts.player2.key();
+        game.total_pot = game
+            .total_pot
+            .checked_add(bet_amount)
+            .ok_or(RpsError::MathOverflow)?;
+        game.deposit_p2 = bet_amount;
+        game.fee_deposit_p2 = entry_fee;
+        game.staked_from_balance_p2 = staked_from_balance;
+        game.referrer_p2 = registered_referrer(&ctx.accounts.player2_referral)?;
 
-// ---------- Helpers ----------
+        // keep your existing next status, unless you want a more specific one
+        game.status = GameStatus::Active;
+        game.last_action_slot = Clock::get()?.slot;
 
-/// 0 = Rock, 1 = Paper, 2 = Scissors
-fn round_winner(m1: u8, m2: u8) -> RoundResult {
-    use RoundResult::*;
-    if m1 == m2 {
-        return Draw;
-    }
-    match (m1, m2) {
-        (0, 2) | (1, 0) | (2, 1) => Player1Win,
-        (2, 0) | (0, 1) | (1, 2) => Player2Win,
-        _ => Draw,
+        require_escrow_healthy(game, &ctx.accounts.game_vault)?;
+        Ok(())
     }
-}
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum RoundResult {
-    Player1Win,
-    Player2Win,
-    Draw,
-}
 
-// ---------- Accounts & State ----------
+        /// Starts a round and opens the 5-second commit window on-chain.
+    ///
+    /// - Can be called by player1 / player2 or their session signers holding
+    ///   `SESSION_PERM_START_ROUND`.
+    /// - Sets commit_deadline_slots[round] based on current slot.
+    /// - Seats with a match root are committed for the round right away; if both
+    ///   are, the reveal window opens at once.
+    /// - If already started or resolved, or a channel dispute is still open, reverts.
+    pub fn start_round(
+        ctx: Context<StartRound>,
+        round_index: u8,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.house_vault, PAUSE_START_ROUND)?;
 
-#[derive(Accounts)]
-pub struct WithdrawHouseFunds<'info> {
-    #[account(
-        mut,
-        address = house_vault.admin @ RpsError::Unauthorized
-    )]
-    pub admin: Signer<'info>,
+        let game = &mut ctx.accounts.game;
+        let current_slot = Clock::get()?.slot;
+        game.acts_as_player1(
+            &ctx.accounts.caller.key(),
+            SESSION_PERM_START_ROUND,
+            current_slot,
+            ctx.accounts.wallet_session.as_deref(),
+        )?;
 
-    #[account(
-        seeds = [b"house_vault"],
-        bump = house_vault.bump,
-    )]
-    pub house_vault: Account<'info, HouseVault>,
+        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+        require!(
+            current_slot > game.channel_challenge_deadline,
+            RpsError::ChannelChallengeActive
+        );
 
-    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump,
-    /// and we only use it as a lamport holder (no deserialization).
-    #[account(
-        mut,
-        seeds = [b"house_vault_sol"],
-        bump,
-        owner = system_program::ID
-    )]
-    pub house_vault_sol: UncheckedAccount<'info>,
+        let idx = round_index as usize;
 
-    pub system_program: Program<'info, System>,
+        // Aynı round'u ikinci defa başlatma
+        require!(
+            game.commit_deadline_slots[idx] == 0,
+            RpsError::CommitWindowAlreadyStarted
+        );
+        require!(
+            !game.round_resolved[idx],
+            RpsError::RoundAlreadyResolved
+        );
+
+        let deadline = current_slot
+            .checked_add(COMMIT_PHASE_SLOTS)
+            .ok_or(RpsError::MathOverflow)?;
+
+        game.commit_deadline_slots[idx] = deadline;
+        game.last_action_slot = current_slot;
+
+        // a committed match root stands in for this round's commit
+        if game.match_root_p1 != [0u8; 32] {
+            game.committed_p1[idx] = true;
+        }
+        if game.match_root_p2 != [0u8; 32] {
+            game.committed_p2[idx] = true;
+        }
+        if game.committed_p1[idx] && game.committed_p2[idx] {
+            game.open_reveal_window(idx, current_slot)?;
+        }
+
+        emit!(RoundStartEvent {
+            game_id: game.game_id,
+            round: round_index,
+            start_slot: current_slot,
+            commit_deadline_slot: deadline,
+        });
+
+        Ok(())
+    }
+
+
+    /// Commit move for a given round.
+    ///
+    /// - Only stores `commitment` (the 32-byte hash)
+    /// - Player identity is inferred from the signer account
+    pub fn commit_move(
+        ctx: Context<CommitMove>,
+        round_index: u8,
+        commitment: [u8; 32],
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let player = &ctx.accounts.player;
+        let current_slot = Clock::get()?.slot;
+
+        // ---- IMPORTANT: correctly classify who this signer is ----
+        let is_p1 = game.acts_as_player1(
+            &player.key(),
+            SESSION_PERM_COMMIT,
+            current_slot,
+            ctx.accounts.wallet_session.as_deref(),
+        )?;
+
+        record_commit(game, is_p1, round_index, commitment, current_slot)
+    }
+
+    /// Commit move submitted by a relayer on a player's behalf.
+    ///
+    /// - The instruction right before this one must be an Ed25519 signature check of
+    ///   `relayed_commit_message(game_id, round_index, commitment)` by player1 or player2.
+    /// - The relayer pays the transaction; otherwise identical to `commit_move`.
+    pub fn commit_move_relayed(
+        ctx: Context<RelayedMove>,
+        round_index: u8,
+        commitment: [u8; 32],
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let current_slot = Clock::get()?.slot;
+
+        let message = relayed_commit_message(&game.game_id, round_index, &commitment);
+        let signer = verified_ed25519_signer(&ctx.accounts.instructions, &message)?;
+        let is_p1 = game.acts_as_player1(&signer, SESSION_PERM_COMMIT, current_slot, None)?;
+
+        record_commit(game, is_p1, round_index, commitment, current_slot)
+    }
+
+
+    /// Reveal move for a given round.
+    ///
+    /// - Verifies hash(move || nonce || game_id || round_index || player_pubkey)
+    ///   matches previously stored commitment.
+    /// - If both players revealed, computes round winner and possibly finishes game.
+    pub fn reveal_move(
+        ctx: Context<RevealMove>,
+        round_index: u8,
+        move_value: u8,
+        nonce: [u8; 32],
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let player = &ctx.accounts.player;
+        let current_slot = Clock::get()?.slot;
+
+        let is_p1 = game.acts_as_player1(
+            &player.key(),
+            SESSION_PERM_REVEAL,
+            current_slot,
+            ctx.accounts.wallet_session.as_deref(),
+        )?;
+
+        record_reveal(game, is_p1, round_index, move_value, nonce, current_slot)
+    }
+
+    /// Reveal move submitted by a relayer on a player's behalf.
+    ///
+    /// - The instruction right before this one must be an Ed25519 signature check of
+    ///   `relayed_reveal_message(game_id, round_index, move_value, nonce)` by player1
+    ///   or player2.
+    /// - The relayer pays the transaction; otherwise identical to `reveal_move`.
+    pub fn reveal_move_relayed(
+        ctx: Context<RelayedMove>,
+        round_index: u8,
+        move_value: u8,
+        nonce: [u8; 32],
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let current_slot = Clock::get()?.slot;
+
+        let message = relayed_reveal_message(&game.game_id, round_index, move_value, &nonce);
+        let signer = verified_ed25519_signer(&ctx.accounts.instructions, &message)?;
+        let is_p1 = game.acts_as_player1(&signer, SESSION_PERM_REVEAL, current_slot, None)?;
+
+        record_reveal(game, is_p1, round_index, move_value, nonce, current_slot)
+    }
+
+    /// Commits moves for the whole match at once ("set and forget").
+    ///
+    /// - `root` is the root of a `MATCH_TREE_DEPTH` Merkle tree whose leaf slot `i` is
+    ///   round `i`'s commitment hash(move || nonce || game_id || round_index ||
+    ///   player_pubkey); unused slots are zero and pairs hash as sha256(left || right).
+    /// - Counts as this seat's commit for every round started afterwards, including
+    ///   for `resolve_commit_timeout`; moves are revealed with `reveal_move_with_proof`.
+    /// - Can be set once per seat, by the player or a session key allowed to commit.
+    pub fn commit_match_root(ctx: Context<CommitMove>, root: [u8; 32]) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let player = &ctx.accounts.player;
+
+        require!(
+            game.status == GameStatus::WaitingForPlayer2 || game.status == GameStatus::Active,
+            RpsError::GameNotActive
+        );
+        require!(root != [0u8; 32], RpsError::InvalidMatchRoot);
+
+        let is_p1 = game.acts_as_player1(
+            &player.key(),
+            SESSION_PERM_COMMIT,
+            Clock::get()?.slot,
+            ctx.accounts.wallet_session.as_deref(),
+        )?;
+
+        let seat_root = if is_p1 {
+            &mut game.match_root_p1
+        } else {
+            &mut game.match_root_p2
+        };
+        require!(*seat_root == [0u8; 32], RpsError::AlreadyCommitted);
+        *seat_root = root;
+
+        emit!(MatchRootCommittedEvent {
+            game_id: game.game_id,
+            player: if is_p1 { game.player1 } else { game.player2 },
+            root,
+        });
+
+        Ok(())
+    }
+
+    /// Reveal move for a round covered by the seat's match root.
+    ///
+    /// - `proof` holds `MATCH_TREE_DEPTH` siblings linking leaf slot `round_index`,
+    ///   hash(move || nonce || game_id || round_index || player_pubkey), to the
+    ///   committed root; the leaf then becomes the round's commitment.
+    /// - Same rules as `reveal_move` otherwise.
+    pub fn reveal_move_with_proof(
+        ctx: Context<RevealMove>,
+        round_index: u8,
+        move_value: u8,
+        nonce: [u8; 32],
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let player = &ctx.accounts.player;
+        let current_slot = Clock::get()?.slot;
+
+        let is_p1 = game.acts_as_player1(
+            &player.key(),
+            SESSION_PERM_REVEAL,
+            current_slot,
+            ctx.accounts.wallet_session.as_deref(),
+        )?;
+
+        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+        require!(proof.len() == MATCH_TREE_DEPTH, RpsError::InvalidMatchProof);
+        let idx = round_index as usize;
+        let (root, seat, committed) = if is_p1 {
+            (game.match_root_p1, game.player1, game.committed_p1[idx])
+        } else {
+            (game.match_root_p2, game.player2, game.committed_p2[idx])
+        };
+        require!(root != [0u8; 32], RpsError::InvalidMatchRoot);
+        require!(committed, RpsError::NotCommittedYet);
+
+        let leaf = move_commitment(move_value, &nonce, &game.game_id, round_index, &seat);
+        require!(
+            merkle_root(leaf, round_index, &proof) == root,
+            RpsError::InvalidMatchProof
+        );
+
+        if is_p1 {
+            game.commitments_p1[idx] = leaf;
+        } else {
+            game.commitments_p2[idx] = leaf;
+        }
+
+        record_reveal(game, is_p1, round_index, move_value, nonce, current_slot)
+    }
+
+
+        /// Resolves a round by timeout after the commit window has expired.
+    ///
+    /// - Can be called by anyone (mediator, any user).
+    /// - Only allowed if:
+    ///   * Game is Active
+    ///   * Commit window was started for that round
+    ///   * Current slot > commit_deadline_slots[round]
+    ///   * Round not already resolved
+    ///   * NOT both players committed (then it's `resolve_reveal_timeout`'s job)
+    /// - Outcome rules:
+    ///   * Only P1 committed  -> P1 wins the round
+    ///   * Only P2 committed  -> P2 wins the round
+    ///   * None committed     -> Draw
+    /// - A seat with a match root counts as committed for rounds started after it.
+    pub fn resolve_commit_timeout(
+        ctx: Context<ResolveCommitTimeout>,
+        round_index: u8,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+    
+        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+    
+        let idx = round_index as usize;
+        let current_slot = Clock::get()?.slot;
+    
+        // Commit penceresi başlatılmış olmalı
+        require!(
+            game.commit_deadline_slots[idx] != 0,
+            RpsError::CommitWindowNotStarted
+        );
+        // Ve commit süresi bitmiş olmalı
+        require!(
+            current_slot > game.commit_deadline_slots[idx],
+            RpsError::CommitPhaseNotExpired
+        );
+        // Aynı round'u ikinci kere resolve etmeyelim
+        require!(
+            !game.round_resolved[idx],
+            RpsError::RoundAlreadyResolved
+        );
+    
+        let c1 = game.committed_p1[idx];
+        let c2 = game.committed_p2[idx];
+    
+        // Eğer iki taraf da commit ettiyse time-out resolve yasak,
+        // mutlaka reveal ile devam etmelisin.
+        require!(!(c1 && c2), RpsError::BothCommittedNoTimeout);
+    
+        let result = if c1 && !c2 {
+            RoundResult::Player1Win
+        } else if !c1 && c2 {
+            RoundResult::Player2Win
+        } else {
+            msg!(
+                "Timeout round {} ended in a TIE (no commits) - round consumed",
+                round_index
+            );
+            RoundResult::Draw
+        };
+
+        game.last_action_slot = current_slot;
+        record_round_result(game, round_index, result)
+    }
+
+    /// Resolves a round by timeout after its reveal window has expired.
+    ///
+    /// - Can be called by anyone (mediator, any user).
+    /// - Only allowed if the game is Active, both seats are committed (per round
+    ///   or by match root), the round is unresolved and the current slot is past
+    ///   its reveal deadline.
+    /// - Outcome rules:
+    ///   * Only P1 revealed   -> P1 wins the round
+    ///   * Only P2 revealed   -> P2 wins the round
+    ///   * None revealed      -> Draw
+    pub fn resolve_reveal_timeout(
+        ctx: Context<ResolveCommitTimeout>,
+        round_index: u8,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+
+        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+        require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+
+        let idx = round_index as usize;
+        let current_slot = Clock::get()?.slot;
+
+        require!(
+            game.committed_p1[idx] && game.committed_p2[idx],
+            RpsError::BothMustCommitFirst
+        );
+        require!(
+            !game.round_resolved[idx],
+            RpsError::RoundAlreadyResolved
+        );
+        require!(
+            current_slot > game.reveal_deadline(idx)?,
+            RpsError::RevealPhaseNotExpired
+        );
+
+        // both reveals would have resolved the round already
+        let result = match (game.revealed_p1[idx], game.revealed_p2[idx]) {
+            (true, false) => RoundResult::Player1Win,
+            (false, true) => RoundResult::Player2Win,
+            _ => {
+                msg!("Timeout round {} ended in a TIE (no reveals) - round consumed", round_index);
+                RoundResult::Draw
+            }
+        };
+
+        game.last_action_slot = current_slot;
+        record_round_result(game, round_index, result)
+    }
+    
+
+    /// Forfeit game - ends the game immediately and declares a winner.
+    ///
+    /// - Called by a player who gives up, or their session key with the forfeit
+    ///   permission.
+    /// - `loser_is_player1` must name the caller's own seat; nobody can forfeit
+    ///   for the other player.
+    /// - The other player wins by default (3 wins credited).
+    /// - Game status is set to Finished, ready for settlement.
+    pub fn forfeit_game(
+        ctx: Context<ForfeitGame>,
+        loser_is_player1: bool,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let caller_is_player1 = game.acts_as_player1(
+            &ctx.accounts.caller.key(),
+            SESSION_PERM_FORFEIT,
+            Clock::get()?.slot,
+            None,
+        )?;
+        require!(
+            loser_is_player1 == caller_is_player1,
+            RpsError::ForfeitForOtherSeat
+        );
+
+        // Game must be Active
+        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+
+        // Set the winner
+        if loser_is_player1 {
+            // PLAYER1 forfeited/disconnected -> PLAYER2 wins
+            game.player2_wins = 3;
+            msg!("PLAYER1 forfeited. PLAYER2 wins!");
+        } else {
+            // PLAYER2 forfeited/disconnected -> PLAYER1 wins
+            game.player1_wins = 3;
+            msg!("PLAYER2 forfeited. PLAYER1 wins!");
+        }
+
+        // Mark game as finished
+        game.status = GameStatus::Finished;
+
+        emit!(GameForfeitEvent {
+            game_id: game.game_id,
+            loser: if loser_is_player1 { game.player1 } else { game.player2 },
+            winner: if loser_is_player1 { game.player2 } else { game.player1 },
+        });
+
+        Ok(())
+    }
+
+    /// Settles an off-chain (state channel) match from its final co-signed state.
+    ///
+    /// - The instruction right before this one must be an Ed25519 check with two
+    ///   signatures, by player1 and player2, over `channel_state_message(game_id, state)`.
+    /// - `state` must be final (3 wins or `MAX_ROUNDS` played) and newer than any
+    ///   channel state already on-chain.
+    /// - Game status is set to Finished; `settle_game` pays out as usual and can go
+    ///   in the same transaction.
+    pub fn settle_channel(ctx: Context<SubmitChannelState>, state: ChannelState) -> Result<()> {
+        require!(state.is_final(), RpsError::InvalidChannelState);
+        apply_channel_state(&mut ctx.accounts.game, &ctx.accounts.instructions, &state)
+    }
+
+    /// Dispute path for a state channel match that stalled.
+    ///
+    /// - Takes the latest co-signed state either player holds (same signature
+    ///   requirements as `settle_channel`) and writes its score on-chain.
+    /// - Each accepted state opens a fresh `CHANNEL_CHALLENGE_SLOTS` window in which a
+    ///   newer co-signed state replaces it; `start_round` is blocked until it ends.
+    /// - Afterwards the match continues with the regular commit / reveal rounds,
+    ///   starting at `round_index = rounds_played`, and their timeouts.
+    pub fn dispute_channel(ctx: Context<SubmitChannelState>, state: ChannelState) -> Result<()> {
+        apply_channel_state(&mut ctx.accounts.game, &ctx.accounts.instructions, &state)
+    }
+
+    /// Cancel game - refunds both players their bets.
+    ///
+    /// - Called when there's an error (blockchain timeout, commit phase expired, etc.)
+    /// - Can be called by anyone (mediator, player, or any user).
+    /// - Both players get their bets refunded (no house fee), into their balance
+    ///   account for seats staked from one; that account must then be passed.
+    /// - Entry fees come back per the game's fee refund policy, depending on whether
+    ///   any round was played: from its fee shard, or `house_vault_sol` once swept.
+    /// - Closes `game_vault` and returns its rent to player1.
+    /// - Game status is set to Cancelled.
+    pub fn cancel_game(ctx: Context<CancelGame>) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+
+        // Game must be Active (not already Finished/Settled/Cancelled)
+        require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+
+        // Mark game as Cancelled
+        game.status = GameStatus::Cancelled;
+
+        let total_pot = game.total_pot;
+
+        // Each player gets back exactly what their seat deposited
+        let player1_refund = game.deposit_p1;
+        let player2_refund = game.deposit_p2;
+
+        msg!("Cancelling game. Refunding player1: {} lamports, player2: {} lamports",
+             player1_refund, player2_refund);
+
+        // Transfer refunds from game vault
+        let game_vault = ctx.accounts.game_vault.to_account_info();
+        let system_program = &ctx.accounts.system_program;
+
+        // Use game_id as seeds for the vault PDA (must match "game_vault" seed used in create_game)
+        let game_id = game.game_id;
+        let vault_seeds: &[&[u8]] = &[
+            b"game_vault",
+            &game_id,
+            &[ctx.bumps.game_vault],
+        ];
+        let vault_signer = &[vault_seeds];
+
+        pay_seat(
+            player1_refund,
+            game.staked_from_balance_p1,
+            &ctx.accounts.player1,
+            &mut ctx.accounts.player1_balance,
+            &game_vault,
+            system_program,
+            vault_signer,
+        )?;
+        pay_seat(
+            player2_refund,
+            game.staked_from_balance_p2,
+            &ctx.accounts.player2,
+            &mut ctx.accounts.player2_balance,
+            &game_vault,
+            system_program,
+            vault_signer,
+        )?;
+        close_vault(&game_vault, &ctx.accounts.player1, system_program, vault_signer)?;
+        let released = player1_refund
+            .checked_add(player2_refund)
+            .ok_or(RpsError::MathOverflow)?;
+        require_escrow_released(released, total_pot, &game_vault)?;
+
+        let stats = &mut ctx.accounts.fee_shard.stats;
+        stats.record_bet_refund(player1_refund)?;
+        stats.record_bet_refund(player2_refund)?;
+
+        let reason = if game.rounds_played == 0 {
+            CancelReason::AbortedBeforePlay
+        } else {
+            CancelReason::AbortedMidGame
+        };
+        let house_vault_seeds: &[&[u8]] = &[b"house_vault_sol", &[ctx.bumps.house_vault_sol]];
+        let mut fee_source = FeeRefundSource {
+            fee_shard: &mut ctx.accounts.fee_shard,
+            house_vault: &ctx.accounts.house_vault,
+            house_vault_sol: &ctx.accounts.house_vault_sol,
+            house_vault_signer: &[house_vault_seeds],
+            system_program,
+        };
+        let player1_fee_refund = refund_entry_fee(
+            game.fee_refund_policy.refund_for(game.fee_deposit_p1, reason)?,
+            game_id,
+            &mut fee_source,
+            game.staked_from_balance_p1,
+            &ctx.accounts.player1,
+            &mut ctx.accounts.player1_balance,
+        )?;
+        let player2_fee_refund = refund_entry_fee(
+            game.fee_refund_policy.refund_for(game.fee_deposit_p2, reason)?,
+            game_id,
+            &mut fee_source,
+            game.staked_from_balance_p2,
+            &ctx.accounts.player2,
+            &mut ctx.accounts.player2_balance,
+        )?;
+
+        emit!(GameCancelledEvent {
+            game_id: game.game_id,
+            player1: game.player1,
+            player2: game.player2,
+            player1_refund,
+            player2_refund,
+            reason,
+            player1_fee_refund,
+            player2_fee_refund,
+        });
+
+        Ok(())
+    }
+
+
+    /// Settles the game: pays the winner and house fee, or returns each deposit on a draw.
+    ///
+    /// - Seats staked from a balance account are paid back into it; that account
+    ///   must then be passed.
+    /// - Can be called by anyone once `status == Finished`.
+    /// - Transfers from `game_vault` account to `winner` & the game's `fee_shard`.
+    /// - Closes `game` and `game_vault` and returns their rent to `player1`.
+    pub fn settle_game(ctx: Context<SettleGame>) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+
+        require!(
+            game.status == GameStatus::Finished,
+            RpsError::GameNotFinished
+        );
+        game.status = GameStatus::Settled;
+
+        let total_pot = game.total_pot;
+        require!(total_pot > 0, RpsError::InvalidBetAmount);
+
+        let player1 = &ctx.accounts.player1;
+        let player2 = &ctx.accounts.player2;
+
+        // Winner determination
+        let winner: Option<Pubkey> = if game.player1_wins > game.player2_wins {
+            Some(game.player1)
+        } else if game.player2_wins > game.player1_wins {
+            Some(game.player2)
+        } else {
+            None
+        };
+
+        let (payout_p1, payout_p2, house_fee, integrator_fee) = if let Some(winner_pk) = winner {
+            let house_fee = game.house_fee(total_pot)?;
+            let integrator_fee = game.integrator_fee(total_pot, house_fee)?;
+
+            let winner_amount = total_pot
+                .checked_sub(house_fee)
+                .and_then(|amount| amount.checked_sub(integrator_fee))
+                .ok_or(RpsError::MathOverflow)?;
+
+            if winner_pk == game.player1 {
+                (winner_amount, 0, house_fee, integrator_fee)
+            } else {
+                (0, winner_amount, house_fee, integrator_fee)
+            }
+        } else {
+            // draw: each seat gets its own deposit back, no rake
+            (game.deposit_p1, game.deposit_p2, 0, 0)
+        };
+
+        // seeds for the game_vault PDA (only needed for legacy system-owned vaults)
+        let game_vault_bump = ctx.bumps.game_vault;
+        let seeds: &[&[u8]] = &[
+            b"game_vault",
+            game.game_id.as_ref(),
+            &[game_vault_bump],
+        ];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let game_vault_ai = ctx.accounts.game_vault.to_account_info();
+        let system_program = &ctx.accounts.system_program;
+
+        // payouts from game_vault, into balance accounts for seats staked from one
+        pay_seat(
+            payout_p1,
+            game.staked_from_balance_p1,
+            player1,
+            &mut ctx.accounts.player1_balance,
+            &game_vault_ai,
+            system_program,
+            signer_seeds,
+        )?;
+        pay_seat(
+            payout_p2,
+            game.staked_from_balance_p2,
+            player2,
+            &mut ctx.accounts.player2_balance,
+            &game_vault_ai,
+            system_program,
+            signer_seeds,
+        )?;
+
+        // Referrers get their share of the rake; the house keeps the rest
+        let referral_reward_p1 = if game.referrer_p1 != Pubkey::default() {
+            game.referral_reward(house_fee, game.deposit_p1)?
+        } else {
+            0
+        };
+        let referral_reward_p2 = if game.referrer_p2 != Pubkey::default() {
+            game.referral_reward(house_fee, game.deposit_p2)?
+        } else {
+            0
+        };
+        let house_cut = house_fee
+            .checked_sub(referral_reward_p1)
+            .and_then(|fee| fee.checked_sub(referral_reward_p2))
+            .ok_or(RpsError::MathOverflow)?;
+
+        pay_from_vault(
+            house_cut,
+            &game_vault_ai,
+            &ctx.accounts.fee_shard.to_account_info(),
+            system_program,
+            signer_seeds,
+        )?;
+        ctx.accounts.fee_shard.stats.record_settlement(house_cut)?;
+
+        if integrator_fee > 0 {
+            let rewards = ctx
+                .accounts
+                .integrator_rewards
+                .as_mut()
+                .ok_or(RpsError::InvalidIntegratorAccount)?;
+            pay_from_vault(
+                integrator_fee,
+                &game_vault_ai,
+                &rewards.to_account_info(),
+                system_program,
+                signer_seeds,
+            )?;
+            rewards.accrue(integrator_fee)?;
+        }
+
+        if game.referrer_p1 != Pubkey::default() && game.referrer_p1 == game.referrer_p2 {
+            // one rewards account for both seats; a second copy would be credited twice
+            require!(
+                ctx.accounts.referrer_rewards_p2.is_none(),
+                RpsError::DuplicateReferrerRewards
+            );
+            let total_reward = referral_reward_p1
+                .checked_add(referral_reward_p2)
+                .ok_or(RpsError::MathOverflow)?;
+            credit_referrer(
+                total_reward,
+                &mut ctx.accounts.referrer_rewards_p1,
+                &game_vault_ai,
+                system_program,
+                signer_seeds,
+            )?;
+        } else {
+            credit_referrer(
+                referral_reward_p1,
+                &mut ctx.accounts.referrer_rewards_p1,
+                &game_vault_ai,
+                system_program,
+                signer_seeds,
+            )?;
+            credit_referrer(
+                referral_reward_p2,
+                &mut ctx.accounts.referrer_rewards_p2,
+                &game_vault_ai,
+                system_program,
+                signer_seeds,
+            )?;
+        }
+
+        // game_vault rent was paid by player1 in create_game
+        close_vault(&game_vault_ai, player1, system_program, signer_seeds)?;
+        let released = [payout_p2, house_fee, integrator_fee]
+            .iter()
+            .try_fold(payout_p1, |sum, amount| sum.checked_add(*amount))
+            .ok_or(RpsError::MathOverflow)?;
+        require_escrow_released(released, total_pot, &game_vault_ai)?;
+
+        emit!(GameSettledEvent {
+            game_id: game.game_id,
+            winner: winner.unwrap_or_default(),
+            payout_p1,
+            payout_p2,
+            house_fee,
+            referral_reward_p1,
+            referral_reward_p2,
+            integrator: game.integrator,
+            integrator_fee,
+        });
+
+        // Anchor will close `game` and send its rent to player1 due to `close = player1`
+        Ok(())
+    }
+
+    /// Checks a game's escrow against its records without changing anything.
+    ///
+    /// - Anyone may call this; meant to be run through transaction simulation.
+    /// - The `EscrowAudit` report is returned via return data.
+    pub fn audit_game(ctx: Context<AuditGame>) -> Result<EscrowAudit> {
+        let rent = Rent::get()?;
+        Ok(ctx.accounts.game.audit_escrow(&ctx.accounts.game_vault, &rent))
+    }
+
+    /// Executes an approved `WithdrawHouseFunds` proposal: pays SOL from the global
+    /// house vault PDA to the proposal's recipient.
+    ///
+    /// - Only a treasurer may execute.
+    /// - Never dips into the rent-exempt minimum or committed liabilities, and
+    ///   counts against the per-epoch withdrawal cap.
+    /// - Signs with the `house_vault_sol` PDA seeds.
+    pub fn withdraw_house_funds(ctx: Context<WithdrawHouseFunds>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        let vault = &mut ctx.accounts.house_vault;
+
+        require_approved(vault, proposal)?;
+        let (amount, recipient) = match proposal.action {
+            ProposalAction::WithdrawHouseFunds { amount, recipient } => (amount, recipient),
+            _ => return err!(RpsError::ProposalActionMismatch),
+        };
+        require_keys_eq!(
+            ctx.accounts.recipient.key(),
+            recipient,
+            RpsError::InvalidRecipient
+        );
+
+        let house_vault_sol = ctx.accounts.house_vault_sol.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(house_vault_sol.data_len());
+        require!(
+            amount <= vault.withdrawable(house_vault_sol.lamports(), reserve),
+            RpsError::InsufficientHouseFunds
+        );
+        let epoch = Clock::get()?.epoch;
+        require!(
+            amount <= vault.remaining_withdrawal_cap(epoch),
+            RpsError::WithdrawalCapExceeded
+        );
+        vault.record_withdrawal(epoch, amount)?;
+
+        let bump = ctx.bumps.house_vault_sol;
+        let seeds: &[&[u8]] = &[
+            b"house_vault_sol",
+            &[bump],
+        ];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        pay_from_vault(
+            amount,
+            &house_vault_sol,
+            &ctx.accounts.recipient.to_account_info(),
+            &ctx.accounts.system_program,
+            signer_seeds,
+        )?;
+
+        emit!(ProposalExecutedEvent {
+            proposal_id: proposal.id,
+            executor: ctx.accounts.treasurer.key(),
+        });
+
+        // Anchor will close `proposal` and send its rent to the proposer
+        Ok(())
+    }
+
+    /// Executes an approved `SetPayees` proposal: replaces the house revenue split.
+    ///
+    /// - Only a treasurer may execute.
+    /// - Shares must add up to 100%; applies from the next distribution on.
+    pub fn set_payees(ctx: Context<SetPayees>) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        let proposal = &ctx.accounts.proposal;
+
+        require_approved(vault, proposal)?;
+        let split = match proposal.action {
+            ProposalAction::SetPayees { split } => split,
+            _ => return err!(RpsError::ProposalActionMismatch),
+        };
+        split.validate()?;
+
+        vault.payee_split = split;
+
+        emit!(PayeesUpdatedEvent { split });
+        emit!(ProposalExecutedEvent {
+            proposal_id: proposal.id,
+            executor: ctx.accounts.treasurer.key(),
+        });
+
+        // Anchor will close `proposal` and send its rent to the proposer
+        Ok(())
+    }
+
+    /// Splits the house vault balance above its rent-exempt minimum and committed
+    /// liabilities between the configured payees.
+    ///
+    /// - Anyone may call this; funds only go to the configured payees.
+    /// - Limited to what is left of the per-epoch withdrawal cap, so repeated calls
+    ///   cannot drain more than the cap allows.
+    /// - Payees are passed as remaining accounts (writable), in configured order.
+    /// - Rounding dust stays in the vault for the next distribution.
+    pub fn distribute_house_funds<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DistributeHouseFunds<'info>>,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.house_vault;
+        let split = vault.payee_split;
+        let count = split.payee_count as usize;
+        require!(count > 0, RpsError::InvalidPayees);
+        require!(
+            ctx.remaining_accounts.len() == count,
+            RpsError::InvalidPayees
+        );
+
+        let house_vault_sol = ctx.accounts.house_vault_sol.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(house_vault_sol.data_len());
+        let epoch = Clock::get()?.epoch;
+        let distributable = vault
+            .withdrawable(house_vault_sol.lamports(), reserve)
+            .min(vault.remaining_withdrawal_cap(epoch));
+
+        let bump = ctx.bumps.house_vault_sol;
+        let seeds: &[&[u8]] = &[
+            b"house_vault_sol",
+            &[bump],
+        ];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let mut amounts = [0u64; MAX_PAYEES];
+        let mut total_distributed: u64 = 0;
+        for (i, (payee, payee_info)) in split.payees[..count]
+            .iter()
+            .zip(ctx.remaining_accounts.iter())
+            .enumerate()
+        {
+            require_keys_eq!(payee_info.key(), payee.recipient, RpsError::InvalidPayees);
+
+            let amount = (distributable as u128)
+                .checked_mul(payee.share_bps as u128)
+                .ok_or(RpsError::MathOverflow)?
+                / BPS_DENOMINATOR as u128;
+            let amount = u64::try_from(amount).map_err(|_| RpsError::MathOverflow)?;
+
+            pay_from_vault(
+                amount,
+                &house_vault_sol,
+                payee_info,
+                &ctx.accounts.system_program,
+                signer_seeds,
+            )?;
+
+            amounts[i] = amount;
+            total_distributed = total_distributed
+                .checked_add(amount)
+                .ok_or(RpsError::MathOverflow)?;
+        }
+
+        require!(
+            total_distributed <= vault.remaining_withdrawal_cap(epoch),
+            RpsError::WithdrawalCapExceeded
+        );
+        vault.record_withdrawal(epoch, total_distributed)?;
+
+        emit!(HouseFundsDistributedEvent {
+            split,
+            amounts,
+            total_distributed,
+            caller: ctx.accounts.caller.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Hands the legacy system-owned `house_vault_sol` PDA over to this program.
+    ///
+    /// - Only the stored `admin` in `HouseVault` is allowed to call this.
+    /// - The PDA signs a system `assign`; its balance and address are unchanged.
+    /// - Afterwards withdrawals and fee moves debit the vault directly (no CPI).
+    pub fn migrate_house_vault_sol(ctx: Context<MigrateHouseVaultSol>) -> Result<()> {
+        let bump = ctx.bumps.house_vault_sol;
+        let seeds: &[&[u8]] = &[
+            b"house_vault_sol",
+            &[bump],
+        ];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let house_vault_sol = ctx.accounts.house_vault_sol.to_account_info();
+        let ix = system_instruction::assign(house_vault_sol.key, &crate::ID);
+        invoke_signed(
+            &ix,
+            &[
+                house_vault_sol,
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        Ok(())
+    }
+
+    /// Grows a `HouseVault` created by an earlier program version to the current layout.
+    ///
+    /// - Only the `admin` stored in the legacy account may call this; it pays the
+    ///   extra rent.
+    /// - Fields were only ever appended, so the old prefix is kept and the new tail
+    ///   starts zeroed; the admin set is bootstrapped 1-of-1 like `init_house_vault`.
+    /// - Upgrade order: deploy, `migrate_house_vault`, `migrate_house_vault_sol`,
+    ///   `init_fee_shard` for every shard, then `migrate_game` for every open game.
+    pub fn migrate_house_vault(ctx: Context<MigrateHouseVault>) -> Result<()> {
+        let info = ctx.accounts.house_vault.to_account_info();
+
+        // legacy layout: discriminator, bump, admin, house_fee_bps
+        let admin = {
+            let data = info.try_borrow_data()?;
+            require!(data.len() >= 8 + 1 + 32, RpsError::NothingToMigrate);
+            Pubkey::try_from(&data[9..41]).map_err(|_| error!(RpsError::NothingToMigrate))?
+        };
+        require_keys_eq!(admin, ctx.accounts.admin.key(), RpsError::Unauthorized);
+
+        grow_account(
+            &info,
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+            HouseVault::DISCRIMINATOR,
+            HouseVault::SPACE,
+        )?;
+
+        let mut data = info.try_borrow_mut_data()?;
+        let mut vault = HouseVault::try_deserialize(&mut &data[..])?;
+        vault.upgrade_legacy();
+        vault.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    /// Grows a `Game` created by an earlier program version to the current layout.
+    ///
+    /// - Anyone may call this for a game that is still open; the caller pays the
+    ///   extra rent.
+    /// - Per-seat deposits are rebuilt from the pot; see `Game::upgrade_legacy`.
+    pub fn migrate_game(ctx: Context<MigrateGame>) -> Result<()> {
+        let info = ctx.accounts.game.to_account_info();
+
+        // the PDA must be a game of this program: seeds are in the legacy prefix
+        {
+            let data = info.try_borrow_data()?;
+            require!(data.len() >= 8 + 1 + 32, RpsError::NothingToMigrate);
+            let expected = Pubkey::create_program_address(
+                &[b"game", &data[9..41], &[data[8]]],
+                &crate::ID,
+            )
+            .map_err(|_| error!(RpsError::NothingToMigrate))?;
+            require_keys_eq!(info.key(), expected, RpsError::NothingToMigrate);
+        }
+
+        grow_account(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            Game::DISCRIMINATOR,
+            Game::SPACE,
+        )?;
+
+        let mut data = info.try_borrow_mut_data()?;
+        let mut game = Game::try_deserialize(&mut &data[..])?;
+        game.upgrade_legacy();
+        game.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    /// Creates one of the `FEE_SHARD_COUNT` fee shard PDAs.
+    ///
+    /// - Only the stored `admin` in `HouseVault` is allowed to call this.
+    /// - Every shard must exist before games routed to it can be created.
+    pub fn init_fee_shard(ctx: Context<InitFeeShard>, shard_index: u8) -> Result<()> {
+        require!(shard_index < FEE_SHARD_COUNT, RpsError::InvalidFeeShard);
+
+        let shard = &mut ctx.accounts.fee_shard;
+        shard.bump = ctx.bumps.fee_shard;
+        shard.index = shard_index;
+        shard.stats = HouseStats::default();
+        Ok(())
+    }
+
+    /// Moves collected entry fees and rake from fee shards into `house_vault_sol`.
+    ///
+    /// - Only the stored `admin` in `HouseVault` is allowed to call this.
+    /// - Shards are passed as remaining accounts (writable), at most
+    ///   `FEE_SHARD_COUNT` per call; each keeps its rent-exempt minimum.
+    pub fn sweep_fee_shards<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepFeeShards<'info>>,
+    ) -> Result<()> {
+        let shards = u8::try_from(ctx.remaining_accounts.len())
+            .ok()
+            .filter(|count| *count <= FEE_SHARD_COUNT)
+            .ok_or(RpsError::InvalidFeeShard)?;
+
+        let house_vault_sol = ctx.accounts.house_vault_sol.to_account_info();
+        let rent = Rent::get()?;
+        let mut total_swept: u64 = 0;
+
+        for shard_info in ctx.remaining_accounts.iter() {
+            let shard: Account<FeeShard> = Account::try_from(shard_info)?;
+            let expected = Pubkey::create_program_address(
+                &[b"fee_shard", &[shard.index], &[shard.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| error!(RpsError::InvalidFeeShard))?;
+            require_keys_eq!(shard_info.key(), expected, RpsError::InvalidFeeShard);
+
+            let reserve = rent.minimum_balance(shard_info.data_len());
+            let amount = shard_info.lamports().saturating_sub(reserve);
+            if amount == 0 {
+                continue;
+            }
+
+            shard_info.sub_lamports(amount)?;
+            house_vault_sol.add_lamports(amount)?;
+            total_swept = total_swept
+                .checked_add(amount)
+                .ok_or(RpsError::MathOverflow)?;
+        }
+
+        emit!(FeeShardsSweptEvent {
+            shards,
+            total_swept,
+            admin: ctx.accounts.admin.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Snapshots the house counters and balances for the current epoch.
+    ///
+    /// - Anyone may call this (paying the report rent), once per epoch.
+    /// - All `FEE_SHARD_COUNT` shards are passed as remaining accounts.
+    pub fn record_epoch_report<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RecordEpochReport<'info>>,
+        epoch: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(epoch == clock.epoch, RpsError::InvalidEpoch);
+        require!(
+            ctx.remaining_accounts.len() == FEE_SHARD_COUNT as usize,
+            RpsError::InvalidFeeShard
+        );
+
+        let rent = Rent::get()?;
+        let mut stats = ctx.accounts.house_vault.stats;
+        let mut fee_shard_balance: u64 = 0;
+        let mut seen: u8 = 0;
+
+        for shard_info in ctx.remaining_accounts.iter() {
+            let shard: Account<FeeShard> = Account::try_from(shard_info)?;
+            let expected = Pubkey::create_program_address(
+                &[b"fee_shard", &[shard.index], &[shard.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| error!(RpsError::InvalidFeeShard))?;
+            require_keys_eq!(shard_info.key(), expected, RpsError::InvalidFeeShard);
+            require!(seen & (1 << shard.index) == 0, RpsError::InvalidFeeShard);
+            seen |= 1 << shard.index;
+
+            stats.accumulate(&shard.stats)?;
+            let reserve = rent.minimum_balance(shard_info.data_len());
+            fee_shard_balance = fee_shard_balance
+                .checked_add(shard_info.lamports().saturating_sub(reserve))
+                .ok_or(RpsError::MathOverflow)?;
+        }
+
+        let house_vault_sol = &ctx.accounts.house_vault_sol;
+        let house_vault_balance = house_vault_sol
+            .lamports()
+            .saturating_sub(rent.minimum_balance(house_vault_sol.data_len()));
+
+        let report = &mut ctx.accounts.epoch_report;
+        report.bump = ctx.bumps.epoch_report;
+        report.epoch = epoch;
+        report.slot = clock.slot;
+        report.stats = stats;
+        report.fee_shard_balance = fee_shard_balance;
+        report.house_vault_balance = house_vault_balance;
+
+        emit!(EpochReportRecordedEvent {
+            epoch,
+            stats,
+            fee_shard_balance,
+            house_vault_balance,
+        });
+
+        Ok(())
+    }
+
+    /// Records the caller's referrer. Each wallet can register exactly once.
+    ///
+    /// - Creates the referrer's rewards account if it doesn't exist yet.
+    /// - Only games created or joined afterwards credit the referrer.
+    pub fn register_referrer(ctx: Context<RegisterReferrer>, referrer: Pubkey) -> Result<()> {
+        let player = ctx.accounts.player.key();
+        require!(
+            referrer != player && referrer != Pubkey::default(),
+            RpsError::InvalidReferrer
+        );
+
+        let referral = &mut ctx.accounts.referral;
+        referral.bump = ctx.bumps.referral;
+        referral.player = player;
+        referral.referrer = referrer;
+
+        let rewards = &mut ctx.accounts.referrer_rewards;
+        if rewards.referrer == Pubkey::default() {
+            rewards.bump = ctx.bumps.referrer_rewards;
+            rewards.referrer = referrer;
+        }
+
+        emit!(ReferrerRegisteredEvent { player, referrer });
+
+        Ok(())
+    }
+
+    /// Creates the caller's integrator rewards account so games can name it.
+    pub fn register_integrator(ctx: Context<RegisterIntegrator>) -> Result<()> {
+        let rewards = &mut ctx.accounts.integrator_rewards;
+        rewards.bump = ctx.bumps.integrator_rewards;
+        rewards.integrator = ctx.accounts.integrator.key();
+
+        emit!(IntegratorRegisteredEvent {
+            integrator: rewards.integrator,
+        });
+
+        Ok(())
+    }
+
+    /// Pays an integrator their accumulated fees.
+    pub fn claim_integrator_rewards(ctx: Context<ClaimIntegratorRewards>) -> Result<()> {
+        let rewards = &mut ctx.accounts.integrator_rewards;
+        let amount = rewards.claimable;
+        require!(amount > 0, RpsError::NothingToClaim);
+
+        // Rewards accounts are program-owned, so their lamports move directly
+        rewards.claimable = 0;
+        rewards.sub_lamports(amount)?;
+        ctx.accounts.integrator.add_lamports(amount)?;
+
+        emit!(IntegratorRewardsClaimedEvent {
+            integrator: rewards.integrator,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Pays a referrer their accumulated rewards.
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let rewards = &mut ctx.accounts.referrer_rewards;
+        let amount = rewards.claimable;
+        require!(amount > 0, RpsError::NothingToClaim);
+
+        // Rewards accounts are program-owned, so their lamports move directly
+        rewards.claimable = 0;
+        rewards.sub_lamports(amount)?;
+        ctx.accounts.referrer.add_lamports(amount)?;
+
+        emit!(ReferralRewardsClaimedEvent {
+            referrer: rewards.referrer,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Funds the caller's balance account, creating it on first use.
+    pub fn deposit_balance(ctx: Context<DepositBalance>, amount: u64) -> Result<()> {
+        require!(amount > 0, RpsError::InvalidBalanceAmount);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.player.to_account_info(),
+                to: ctx.accounts.player_balance.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_ctx, amount)?;
+
+        let balance = &mut ctx.accounts.player_balance;
+        if balance.owner == Pubkey::default() {
+            balance.bump = ctx.bumps.player_balance;
+            balance.owner = ctx.accounts.player.key();
+        }
+        balance.amount = balance
+            .amount
+            .checked_add(amount)
+            .ok_or(RpsError::MathOverflow)?;
+
+        emit!(BalanceDepositedEvent {
+            player: balance.owner,
+            amount,
+            balance: balance.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws from the caller's balance account to their wallet.
+    ///
+    /// - Stakes already in games are not part of the balance.
+    pub fn withdraw_balance(ctx: Context<WithdrawBalance>, amount: u64) -> Result<()> {
+        require!(amount > 0, RpsError::InvalidBalanceAmount);
+
+        // Balance accounts are program-owned, so their lamports move directly
+        let balance = &mut ctx.accounts.player_balance;
+        balance.amount = balance
+            .amount
+            .checked_sub(amount)
+            .ok_or(RpsError::InsufficientBalance)?;
+        balance.sub_lamports(amount)?;
+        ctx.accounts.player.add_lamports(amount)?;
+
+        emit!(BalanceWithdrawnEvent {
+            player: balance.owner,
+            amount,
+            balance: balance.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Authorize a delegated session signer for this game.
+    ///
+    /// - The `player` must be either player1 or player2.
+    /// - `session_pubkey` may do what `permissions` (`SESSION_PERM_*`) allow for this
+    ///   game until `expiry_slot`; replaces any earlier session of that player.
+    pub fn authorize_session_signer(
+        ctx: Context<ManageSessionSigner>,
+        session_pubkey: Pubkey,
+        expiry_slot: u64,
+        permissions: u8,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let player_key = ctx.accounts.player.key();
+
+        // Optional: only allow while game is not finished
+        require!(
+            game.status == GameStatus::WaitingForPlayer2 || game.status == GameStatus::Active,
+            RpsError::InvalidGameState
+        );
+        require!(
+            session_pubkey != Pubkey::default()
+                && permissions != 0
+                && permissions & !SESSION_PERMS == 0
+                && expiry_slot > Clock::get()?.slot,
+            RpsError::InvalidSession
+        );
+
+        if player_key == game.player1 {
+            game.session_p1 = session_pubkey;
+            game.session_expiry_p1 = expiry_slot;
+            game.session_perms_p1 = permissions;
+        } else if player_key == game.player2 {
+            game.session_p2 = session_pubkey;
+            game.session_expiry_p2 = expiry_slot;
+            game.session_perms_p2 = permissions;
+        } else {
+            return Err(RpsError::NotAPlayer.into());
+        }
+
+        emit!(SessionAuthorizedEvent {
+            game_id: game.game_id,
+            player: player_key,
+            session: session_pubkey,
+            expiry_slot,
+            permissions,
+        });
+
+        Ok(())
+    }
+
+    /// Revokes the caller's session signer for this game, e.g. after a key leak.
+    ///
+    /// - The `player` must be either player1 or player2.
+    pub fn revoke_session_signer(ctx: Context<ManageSessionSigner>) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let player_key = ctx.accounts.player.key();
+
+        let session = if player_key == game.player1 {
+            let session = game.session_p1;
+            game.session_p1 = Pubkey::default();
+            game.session_expiry_p1 = 0;
+            game.session_perms_p1 = 0;
+            session
+        } else if player_key == game.player2 {
+            let session = game.session_p2;
+            game.session_p2 = Pubkey::default();
+            game.session_expiry_p2 = 0;
+            game.session_perms_p2 = 0;
+            session
+        } else {
+            return Err(RpsError::NotAPlayer.into());
+        };
+
+        emit!(SessionRevokedEvent {
+            game_id: game.game_id,
+            player: player_key,
+            session,
+        });
+
+        Ok(())
+    }
+
+    /// Authorizes an ephemeral key to play for the caller across all their games.
+    ///
+    /// - Valid until `expiry_slot`, at most `MAX_WALLET_SESSION_SLOTS` ahead.
+    /// - `permissions` is a subset of `WALLET_SESSION_PERMS`; `max_bet` (0 = any)
+    ///   limits the games the key may act in.
+    /// - Optionally tops up the key with `top_up` lamports for transaction fees.
+    /// - One session per wallet; close it before opening another.
+    pub fn open_wallet_session(
+        ctx: Context<OpenWalletSession>,
+        session_key: Pubkey,
+        expiry_slot: u64,
+        permissions: u8,
+        max_bet: u64,
+        top_up: u64,
+    ) -> Result<()> {
+        let current_slot = Clock::get()?.slot;
+        let max_expiry_slot = current_slot
+            .checked_add(MAX_WALLET_SESSION_SLOTS)
+            .ok_or(RpsError::MathOverflow)?;
+        require!(
+            session_key != Pubkey::default()
+                && session_key != ctx.accounts.owner.key()
+                && permissions != 0
+                && permissions & !WALLET_SESSION_PERMS == 0
+                && expiry_slot > current_slot
+                && expiry_slot <= max_expiry_slot,
+            RpsError::InvalidSession
+        );
+        require_keys_eq!(
+            ctx.accounts.session_signer.key(),
+            session_key,
+            RpsError::InvalidSession
+        );
+
+        let session = &mut ctx.accounts.wallet_session;
+        session.bump = ctx.bumps.wallet_session;
+        session.owner = ctx.accounts.owner.key();
+        session.session_key = session_key;
+        session.expiry_slot = expiry_slot;
+        session.permissions = permissions;
+        session.max_bet = max_bet;
+
+        if top_up > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.owner.to_account_info(),
+                    to: ctx.accounts.session_signer.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_ctx, top_up)?;
+        }
+
+        emit!(WalletSessionOpenedEvent {
+            owner: session.owner,
+            session_key,
+            expiry_slot,
+            permissions,
+            max_bet,
+            top_up,
+        });
+
+        Ok(())
+    }
+
+    /// Sends more lamports to the caller's wallet session key for transaction fees.
+    pub fn top_up_wallet_session(ctx: Context<TopUpWalletSession>, amount: u64) -> Result<()> {
+        require!(amount > 0, RpsError::InvalidBalanceAmount);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.owner.to_account_info(),
+                to: ctx.accounts.session_signer.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_ctx, amount)?;
+        Ok(())
+    }
+
+    /// Ends the caller's wallet session.
+    ///
+    /// - If the session key also signs, its remaining lamports are refunded to the owner.
+    /// - Anchor closes the session account and returns its rent to the owner.
+    pub fn close_wallet_session(ctx: Context<CloseWalletSession>) -> Result<()> {
+        let refunded = match ctx.accounts.session_signer.as_ref() {
+            Some(session_signer) => {
+                let amount = session_signer.lamports();
+                let cpi_ctx = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: session_signer.to_account_info(),
+                        to: ctx.accounts.owner.to_account_info(),
+                    },
+                );
+                system_program::transfer(cpi_ctx, amount)?;
+                amount
+            }
+            None => 0,
+        };
+
+        emit!(WalletSessionClosedEvent {
+            owner: ctx.accounts.owner.key(),
+            session_key: ctx.accounts.wallet_session.session_key,
+            refunded,
+        });
+
+        // Anchor will close `wallet_session` and send its rent to the owner
+        Ok(())
+    }
+
+    /// Admin force refund - emergency function to refund stuck games.
+    ///
+    /// - Executes an approved `AdminForceRefund` proposal for this game; only a
+    ///   refunder may execute.
+    /// - Works on games that still hold their deposits: WaitingForPlayer2, Active
+    ///   or Finished (settled games are closed; cancelled ones already refunded).
+    /// - Repays each player their recorded deposit from game_vault, into their
+    ///   balance account for seats staked from one.
+    /// - If the proposal says so, also refunds entry fees in full from the game's
+    ///   fee shard, or `house_vault_sol` once swept, failing if neither can cover them.
+    /// - Closes game and game_vault accounts and returns rent to player1.
+    /// - Use this when normal cancel_game fails (e.g., player2 is System Program).
+    pub fn admin_force_refund(ctx: Context<AdminForceRefund>) -> Result<()> {
+        let game = &ctx.accounts.game;
+        let proposal = &ctx.accounts.proposal;
+
+        require_approved(&ctx.accounts.house_vault, proposal)?;
+        let refund_entry_fees = match proposal.action {
+            ProposalAction::AdminForceRefund {
+                game_id,
+                refund_entry_fees,
+            } => {
+                require!(game_id == game.game_id, RpsError::ProposalActionMismatch);
+                refund_entry_fees
+            }
+            _ => return err!(RpsError::ProposalActionMismatch),
+        };
+
+        msg!("Admin force refund for game: {:?}", game.game_id);
+        msg!("Current status: {:?}", game.status as u8);
+
+        let [(player1_refund, player1_fee_refund), (player2_refund, player2_fee_refund)] =
+            game.force_refund_amounts(refund_entry_fees)?;
+
+        let game_id = game.game_id;
+        let escrowed = game.total_pot;
+        let player1 = ctx.accounts.player1.to_account_info();
+        let game_vault = ctx.accounts.game_vault.to_account_info();
+        let system_program = &ctx.accounts.system_program;
+
+        let vault_bump = ctx.bumps.game_vault;
+        let vault_seeds: &[&[u8]] = &[
+            b"game_vault",
+            game.game_id.as_ref(),
+            &[vault_bump],
+        ];
+        let vault_signer: &[&[&[u8]]] = &[vault_seeds];
+        let house_vault_seeds: &[&[u8]] = &[b"house_vault_sol", &[ctx.bumps.house_vault_sol]];
+        let mut fee_source = FeeRefundSource {
+            fee_shard: &mut ctx.accounts.fee_shard,
+            house_vault: &ctx.accounts.house_vault,
+            house_vault_sol: &ctx.accounts.house_vault_sol,
+            house_vault_signer: &[house_vault_seeds],
+            system_program,
+        };
+
+        // Bets come back from game_vault, entry fees from the fee shard that took them
+        pay_seat(
+            player1_refund,
+            game.staked_from_balance_p1,
+            &player1,
+            &mut ctx.accounts.player1_balance,
+            &game_vault,
+            system_program,
+            vault_signer,
+        )?;
+        let paid = refund_entry_fee(
+            player1_fee_refund,
+            game_id,
+            &mut fee_source,
+            game.staked_from_balance_p1,
+            &player1,
+            &mut ctx.accounts.player1_balance,
+        )?;
+        require!(paid == player1_fee_refund, RpsError::InsufficientHouseFunds);
+        if player2_refund > 0 || player2_fee_refund > 0 {
+            let player2 = ctx
+                .accounts
+                .player2
+                .as_ref()
+                .ok_or(RpsError::InvalidPlayerAccount)?;
+            pay_seat(
+                player2_refund,
+                game.staked_from_balance_p2,
+                player2,
+                &mut ctx.accounts.player2_balance,
+                &game_vault,
+                system_program,
+                vault_signer,
+            )?;
+            let paid = refund_entry_fee(
+                player2_fee_refund,
+                game_id,
+                &mut fee_source,
+                game.staked_from_balance_p2,
+                player2,
+                &mut ctx.accounts.player2_balance,
+            )?;
+            require!(paid == player2_fee_refund, RpsError::InsufficientHouseFunds);
+        }
+
+        // Leftover vault rent goes to player1 as game_vault closes
+        close_vault(&game_vault, &player1, system_program, vault_signer)?;
+        let released = player1_refund
+            .checked_add(player2_refund)
+            .ok_or(RpsError::MathOverflow)?;
+        require_escrow_released(released, escrowed, &game_vault)?;
+
+        let shard_stats = &mut ctx.accounts.fee_shard.stats;
+        shard_stats.record_bet_refund(player1_refund)?;
+        shard_stats.record_bet_refund(player2_refund)?;
+
+        msg!(
+            "Refunded player1: {} + {} fee, player2: {} + {} fee",
+            player1_refund,
+            player1_fee_refund,
+            player2_refund,
+            player2_fee_refund
+        );
+
+        emit!(AdminForceRefundEvent {
+            game_id,
+            player1: game.player1,
+            player2: game.player2,
+            player1_refund,
+            player2_refund,
+            player1_fee_refund,
+            player2_fee_refund,
+            admin: ctx.accounts.refunder.key(),
+        });
+        emit!(ProposalExecutedEvent {
+            proposal_id: proposal.id,
+            executor: ctx.accounts.refunder.key(),
+        });
+
+        // Anchor will close game account and send rent to player1,
+        // and the proposal account with its rent to the proposer
+        Ok(())
+    }
+
+    /// Allows player1 to cancel a game that never started (player2 never joined)
+    /// after TIMEOUT_SLOTS have passed since creation.
+    ///
+    /// - Refunds player1's bet from game_vault, and the entry fee per the game's fee
+    ///   refund policy from its fee shard, or `house_vault_sol` once swept; both go
+    ///   back into player1's balance account if the seat was staked from it.
+    /// - Closes game and game_vault accounts and returns rent to player1.
+    pub fn cancel_game_if_timed_out(ctx: Context<CancelGameIfTimedOut>) -> Result<()> {
+        let game = &ctx.accounts.game;
+
+        require!(
+            game.status == GameStatus::WaitingForPlayer2,
+            RpsError::GameNotCancellable
+        );
+        require_keys_eq!(ctx.accounts.player1.key(), game.player1, RpsError::NotAPlayer);
+
+        let current_slot = Clock::get()?.slot;
+        require!(
+            current_slot >= game.created_slot + TIMEOUT_SLOTS,
+            RpsError::NotTimedOut
+        );
+
+        // refund pot plus vault rent to player1, closing game_vault
+        let bump = ctx.bumps.game_vault;
+        let seeds: &[&[u8]] = &[
+            b"game_vault",
+            game.game_id.as_ref(),
+            &[bump],
+        ];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let game_vault = ctx.accounts.game_vault.to_account_info();
+        let player1 = ctx.accounts.player1.to_account_info();
+        let system_program = &ctx.accounts.system_program;
+
+        pay_seat(
+            game.deposit_p1,
+            game.staked_from_balance_p1,
+            &player1,
+            &mut ctx.accounts.player1_balance,
+            &game_vault,
+            system_program,
+            signer_seeds,
+        )?;
+        close_vault(&game_vault, &player1, system_program, signer_seeds)?;
+        require_escrow_released(game.deposit_p1, game.total_pot, &game_vault)?;
+        ctx.accounts.fee_shard.stats.record_bet_refund(game.deposit_p1)?;
+
+        let reason = CancelReason::NoOpponent;
+        let house_vault_seeds: &[&[u8]] = &[b"house_vault_sol", &[ctx.bumps.house_vault_sol]];
+        let mut fee_source = FeeRefundSource {
+            fee_shard: &mut ctx.accounts.fee_shard,
+            house_vault: &ctx.accounts.house_vault,
+            house_vault_sol: &ctx.accounts.house_vault_sol,
+            house_vault_signer: &[house_vault_seeds],
+            system_program,
+        };
+        let player1_fee_refund = refund_entry_fee(
+            game.fee_refund_policy.refund_for(game.fee_deposit_p1, reason)?,
+            game.game_id,
+            &mut fee_source,
+            game.staked_from_balance_p1,
+            &player1,
+            &mut ctx.accounts.player1_balance,
+        )?;
+
+        emit!(GameCancelledEvent {
+            game_id: game.game_id,
+            player1: game.player1,
+            player2: game.player2,
+            player1_refund: game.deposit_p1,
+            player2_refund: 0,
+            reason,
+            player1_fee_refund,
+            player2_fee_refund: 0,
+        });
+
+        // Anchor will close game and send its rent to player1
+        Ok(())
+    }
+
+    /// Escape hatch: returns each player exactly what they deposited into
+    /// `game_vault` once the house has been paused, or the game left idle, for the
+    /// game's emergency exit delay.
+    ///
+    /// - Can be called by anyone; works while the house is paused.
+    /// - Seats staked from a balance account are refunded into it.
+    /// - Idle means no join, round start, commit, reveal, timeout or channel update;
+    ///   see `Game::emergency_exit_unlock_slot`.
+    /// - Only for games still WaitingForPlayer2 or Active (Finished games can
+    ///   always be settled permissionlessly).
+    /// - Closes game and game_vault accounts and returns rent to player1.
+    pub fn emergency_exit(ctx: Context<EmergencyExit>) -> Result<()> {
+        let game = &ctx.accounts.game;
+
+        require!(
+            game.status == GameStatus::WaitingForPlayer2 || game.status == GameStatus::Active,
+            RpsError::GameNotCancellable
+        );
+
+        let current_slot = Clock::get()?.slot;
+        let unlock_slot = game.emergency_exit_unlock_slot(&ctx.accounts.house_vault)?;
+        require!(current_slot >= unlock_slot, RpsError::EmergencyExitLocked);
+
+        let bump = ctx.bumps.game_vault;
+        let seeds: &[&[u8]] = &[
+            b"game_vault",
+            game.game_id.as_ref(),
+            &[bump],
+        ];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let game_vault = ctx.accounts.game_vault.to_account_info();
+        let player1 = ctx.accounts.player1.to_account_info();
+        let system_program = &ctx.accounts.system_program;
+
+        pay_seat(
+            game.deposit_p1,
+            game.staked_from_balance_p1,
+            &player1,
+            &mut ctx.accounts.player1_balance,
+            &game_vault,
+            system_program,
+            signer_seeds,
+        )?;
+        if game.deposit_p2 > 0 {
+            let player2 = ctx
+                .accounts
+                .player2
+                .as_ref()
+                .ok_or(RpsError::InvalidPlayerAccount)?;
+            pay_seat(
+                game.deposit_p2,
+                game.staked_from_balance_p2,
+                player2,
+                &mut ctx.accounts.player2_balance,
+                &game_vault,
+                system_program,
+                signer_seeds,
+            )?;
+        }
+
+        // game_vault rent was paid by player1 in create_game
+        close_vault(&game_vault, &player1, system_program, signer_seeds)?;
+        let released = game
+            .deposit_p1
+            .checked_add(game.deposit_p2)
+            .ok_or(RpsError::MathOverflow)?;
+        require_escrow_released(released, game.total_pot, &game_vault)?;
+
+        let stats = &mut ctx.accounts.fee_shard.stats;
+        stats.record_bet_refund(game.deposit_p1)?;
+        stats.record_bet_refund(game.deposit_p2)?;
+
+        emit!(EmergencyExitEvent {
+            game_id: game.game_id,
+            player1: game.player1,
+            player2: game.player2,
+            player1_refund: game.deposit_p1,
+            player2_refund: game.deposit_p2,
+        });
+
+        // Anchor will close game and send its rent to player1
+        Ok(())
+    }
+}
+
+// ---------- Helpers ----------
+
+/// Stores a player's commitment for a round once the caller is known to act for
+/// player1 (`is_p1`) or player2.
+fn record_commit(
+    game: &mut Game,
+    is_p1: bool,
+    round_index: u8,
+    commitment: [u8; 32],
+    current_slot: u64,
+) -> Result<()> {
+    require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+    require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+
+    let idx = round_index as usize;
+
+    // koray - 28.11.2025 - Commit window MUST have been started by start_round.
+    let deadline = game.commit_deadline_slots[idx];
+    require!(
+        deadline != 0,
+        RpsError::CommitWindowNotStarted
+    );
+    require!(
+        current_slot <= deadline,
+        RpsError::CommitPhaseExpired
+    );
+
+    if is_p1 {
+        require!(!game.committed_p1[idx], RpsError::AlreadyCommitted);
+        game.commitments_p1[idx] = commitment;
+        game.committed_p1[idx] = true;
+    } else {
+        require!(!game.committed_p2[idx], RpsError::AlreadyCommitted);
+        game.commitments_p2[idx] = commitment;
+        game.committed_p2[idx] = true;
+    }
+    game.last_action_slot = current_slot;
+
+    // When both commits are in, start the reveal window and notify clients.
+    let both_committed = game.committed_p1[idx] && game.committed_p2[idx];
+    let reveal_deadline_slot = if both_committed {
+        game.open_reveal_window(idx, current_slot)?
+    } else {
+        0
+    };
+
+    emit!(RoundPhaseEvent {
+        game_id: game.game_id,
+        round: round_index,
+        current_slot,
+        commit_deadline_slot: game.commit_deadline_slots[idx],
+        reveal_deadline_slot,
+        both_committed,
+    });
+
+    Ok(())
+}
+
+/// Checks a revealed move against the stored commitment and resolves the round
+/// once both moves are in.
+fn record_reveal(
+    game: &mut Game,
+    is_p1: bool,
+    round_index: u8,
+    move_value: u8,
+    nonce: [u8; 32],
+    current_slot: u64,
+) -> Result<()> {
+    require!(game.status == GameStatus::Active, RpsError::GameNotActive);
+    require!((round_index as usize) < MAX_ROUNDS, RpsError::InvalidRound);
+    require!(move_value <= 2, RpsError::InvalidMove);
+
+    let idx = round_index as usize;
+
+    // Both commits must be in and the round must not be resolved yet.
+    require!(
+        game.committed_p1[idx] && game.committed_p2[idx],
+        RpsError::BothMustCommitFirst
+    );
+    require!(
+        !game.round_resolved[idx],
+        RpsError::RoundAlreadyResolved
+    );
+    require!(
+        current_slot <= game.reveal_deadline(idx)?,
+        RpsError::RevealPhaseExpired
+    );
+
+    // recompute commitment hash
+    let commitment_pubkey = if is_p1 { game.player1 } else { game.player2 };
+    let hash_bytes =
+        move_commitment(move_value, &nonce, &game.game_id, round_index, &commitment_pubkey);
+
+    if is_p1 {
+        require!(game.committed_p1[idx], RpsError::NotCommittedYet);
+        require!(!game.revealed_p1[idx], RpsError::AlreadyRevealed);
+        require!(
+            game.commitments_p1[idx] == hash_bytes,
+            RpsError::CommitmentMismatch
+        );
+        game.moves_p1[idx] = move_value;
+        game.revealed_p1[idx] = true;
+    } else {
+        require!(game.committed_p2[idx], RpsError::NotCommittedYet);
+        require!(!game.revealed_p2[idx], RpsError::AlreadyRevealed);
+        require!(
+            game.commitments_p2[idx] == hash_bytes,
+            RpsError::CommitmentMismatch
+        );
+        game.moves_p2[idx] = move_value;
+        game.revealed_p2[idx] = true;
+    }
+    game.last_action_slot = current_slot;
+
+    // resolution logic
+    if game.revealed_p1[idx] && game.revealed_p2[idx] {
+        let m1 = game.moves_p1[idx];
+        let m2 = game.moves_p2[idx];
+        let round_result = round_winner(m1, m2);
+        if round_result == RoundResult::Draw {
+            msg!("Round {} ended in a TIE", round_index);
+        }
+        record_round_result(game, round_index, round_result)?;
+    }
+
+    Ok(())
+}
+
+/// Scores a round, marks it resolved and finishes the game once a player has three
+/// wins or every round has been played.
+fn record_round_result(game: &mut Game, round_index: u8, result: RoundResult) -> Result<()> {
+    match result {
+        RoundResult::Player1Win => {
+            game.player1_wins = game
+                .player1_wins
+                .checked_add(1)
+                .ok_or(RpsError::MathOverflow)?;
+        }
+        RoundResult::Player2Win => {
+            game.player2_wins = game
+                .player2_wins
+                .checked_add(1)
+                .ok_or(RpsError::MathOverflow)?;
+        }
+        // a draw still uses up the round
+        RoundResult::Draw => {}
+    }
+    game.rounds_played = game
+        .rounds_played
+        .checked_add(1)
+        .ok_or(RpsError::MathOverflow)?;
+    game.round_resolved[round_index as usize] = true;
+
+    if game.player1_wins >= 3
+        || game.player2_wins >= 3
+        || game.rounds_played >= MAX_ROUNDS as u8
+    {
+        game.status = GameStatus::Finished;
+    }
+
+    emit!(RoundResultEvent {
+        game_id: game.game_id,
+        round: round_index,
+        player1_wins: game.player1_wins,
+        player2_wins: game.player2_wins,
+        rounds_played: game.rounds_played,
+        status: game.status,
+    });
+
+    Ok(())
+}
+
+/// Commitment for one round: hash(move || nonce || game_id || round_index || player_pubkey).
+fn move_commitment(
+    move_value: u8,
+    nonce: &[u8; 32],
+    game_id: &[u8; 32],
+    round_index: u8,
+    player: &Pubkey,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([move_value]);
+    hasher.update(nonce);
+    hasher.update(game_id);
+    hasher.update([round_index]);
+    hasher.update(player.as_ref());
+    let hash = hasher.finalize();
+    let mut hash_bytes = [0u8; 32];
+    hash_bytes.copy_from_slice(&hash[..]);
+    hash_bytes
+}
+
+/// Folds the leaf in slot `index` up a Merkle `proof`; bit `i` of `index` says
+/// whether the node is the right child at level `i`.
+fn merkle_root(leaf: [u8; 32], index: u8, proof: &[[u8; 32]]) -> [u8; 32] {
+    proof.iter().enumerate().fold(leaf, |node, (level, sibling)| {
+        if (index >> level) & 1 == 1 {
+            merkle_node(sibling, &node)
+        } else {
+            merkle_node(&node, sibling)
+        }
+    })
+}
+
+/// Inner Merkle node: sha256(left || right).
+fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    let hash = hasher.finalize();
+    let mut hash_bytes = [0u8; 32];
+    hash_bytes.copy_from_slice(&hash[..]);
+    hash_bytes
+}
+
+/// Message a player signs to have a relayer submit `commit_move_relayed`:
+/// `"rps:commit" || program_id || game_id || round_index || commitment`.
+pub fn relayed_commit_message(game_id: &[u8; 32], round_index: u8, commitment: &[u8; 32]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(10 + 32 + 32 + 1 + 32);
+    message.extend_from_slice(b"rps:commit");
+    message.extend_from_slice(crate::ID.as_ref());
+    message.extend_from_slice(game_id);
+    message.push(round_index);
+    message.extend_from_slice(commitment);
+    message
+}
+
+/// Message a player signs to have a relayer submit `reveal_move_relayed`:
+/// `"rps:reveal" || program_id || game_id || round_index || move_value || nonce`.
+pub fn relayed_reveal_message(
+    game_id: &[u8; 32],
+    round_index: u8,
+    move_value: u8,
+    nonce: &[u8; 32],
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(10 + 32 + 32 + 1 + 1 + 32);
+    message.extend_from_slice(b"rps:reveal");
+    message.extend_from_slice(crate::ID.as_ref());
+    message.extend_from_slice(game_id);
+    message.push(round_index);
+    message.push(move_value);
+    message.extend_from_slice(nonce);
+    message
+}
+
+/// Message both players sign for each state channel update:
+/// `"rps:state" || program_id || game_id || seq || rounds_played || player1_wins ||
+/// player2_wins`.
+pub fn channel_state_message(game_id: &[u8; 32], state: &ChannelState) -> Vec<u8> {
+    let mut message = Vec::with_capacity(9 + 32 + 32 + 8 + 3);
+    message.extend_from_slice(b"rps:state");
+    message.extend_from_slice(crate::ID.as_ref());
+    message.extend_from_slice(game_id);
+    message.extend_from_slice(&state.seq.to_le_bytes());
+    message.push(state.rounds_played);
+    message.push(state.player1_wins);
+    message.push(state.player2_wins);
+    message
+}
+
+/// Returns the key that signed `message`, from a single-signature Ed25519
+/// instruction placed right before the current one.
+fn verified_ed25519_signer(instructions: &AccountInfo, message: &[u8]) -> Result<Pubkey> {
+    let signers = verified_ed25519_signers(instructions, message)?;
+    require!(signers.len() == 1, RpsError::InvalidRelayedSignature);
+    Ok(signers[0])
+}
+
+/// Returns the keys that signed `message`, taken from the Ed25519 signature-verify
+/// instruction placed right before the current one.
+///
+/// The Ed25519 program has already checked the signatures when we run; see
+/// `ed25519_signers` for what we check on top.
+fn verified_ed25519_signers(instructions: &AccountInfo, message: &[u8]) -> Result<Vec<Pubkey>> {
+    let current_index = load_current_index_checked(instructions)?;
+    require!(current_index > 0, RpsError::InvalidRelayedSignature);
+    let ix = load_instruction_at_checked(current_index as usize - 1, instructions)?;
+    require_keys_eq!(ix.program_id, ed25519_program::ID, RpsError::InvalidRelayedSignature);
+    ed25519_signers(&ix.data, message)
+}
+
+/// Parses the data of an Ed25519 signature-verify instruction into its signer keys,
+/// making sure every key and message live in that data and that each message is
+/// the one we expect.
+fn ed25519_signers(data: &[u8], message: &[u8]) -> Result<Vec<Pubkey>> {
+    // header: num_signatures (u8), padding (u8), then one offsets record of 7 u16s
+    // per signature
+    require!(data.len() >= 2, RpsError::InvalidRelayedSignature);
+    let count = data[0] as usize;
+    require!(
+        count > 0 && data.len() >= 2 + count * 14,
+        RpsError::InvalidRelayedSignature
+    );
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+
+    let mut signers = Vec::with_capacity(count);
+    for record in (0..count).map(|i| 2 + i * 14) {
+        let signature_ix = read_u16(record + 2);
+        let public_key_offset = read_u16(record + 4) as usize;
+        let public_key_ix = read_u16(record + 6);
+        let message_offset = read_u16(record + 8) as usize;
+        let message_size = read_u16(record + 10) as usize;
+        let message_ix = read_u16(record + 12);
+
+        // u16::MAX points at the Ed25519 instruction itself
+        require!(
+            signature_ix == u16::MAX && public_key_ix == u16::MAX && message_ix == u16::MAX,
+            RpsError::InvalidRelayedSignature
+        );
+        let public_key = data
+            .get(public_key_offset..public_key_offset + 32)
+            .ok_or(RpsError::InvalidRelayedSignature)?;
+        let signed_message = data
+            .get(message_offset..message_offset + message_size)
+            .ok_or(RpsError::InvalidRelayedSignature)?;
+        require!(signed_message == message, RpsError::InvalidRelayedSignature);
+
+        signers.push(
+            Pubkey::try_from(public_key).map_err(|_| error!(RpsError::InvalidRelayedSignature))?,
+        );
+    }
+    Ok(signers)
+}
+
+/// Writes a co-signed state channel `state` onto `game`.
+///
+/// - Needs signatures from both players; see `Game::apply_channel_state` for the
+///   rest of the rules.
+fn apply_channel_state(
+    game: &mut Game,
+    instructions: &AccountInfo,
+    state: &ChannelState,
+) -> Result<()> {
+    let message = channel_state_message(&game.game_id, state);
+    let signers = verified_ed25519_signers(instructions, &message)?;
+    require!(
+        signers.len() == 2 && signers.contains(&game.player1) && signers.contains(&game.player2),
+        RpsError::InvalidRelayedSignature
+    );
+
+    game.apply_channel_state(state, Clock::get()?.slot)?;
+
+    emit!(ChannelStateSubmittedEvent {
+        game_id: game.game_id,
+        seq: state.seq,
+        player1_wins: game.player1_wins,
+        player2_wins: game.player2_wins,
+        rounds_played: game.rounds_played,
+        status: game.status,
+        challenge_deadline_slot: game.channel_challenge_deadline,
+    });
+
+    Ok(())
+}
+
+/// Checked add for `HouseStats` counters.
+fn add_to_counter(counter: &mut u64, amount: u64) -> Result<()> {
+    *counter = counter.checked_add(amount).ok_or(RpsError::MathOverflow)?;
+    Ok(())
+}
+
+/// Accounts entry fee refunds are paid from: the game's fee shard first, then
+/// `house_vault_sol` for whatever was already swept out of the shard.
+struct FeeRefundSource<'a, 'info> {
+    fee_shard: &'a mut Account<'info, FeeShard>,
+    house_vault: &'a HouseVault,
+    house_vault_sol: &'a AccountInfo<'info>,
+    house_vault_signer: &'a [&'a [&'a [u8]]],
+    system_program: &'a Program<'info, System>,
+}
+
+/// Pays up to `amount` of a cancelled game's entry fee back out of the fee shard
+/// that collected it, topping up from `house_vault_sol` once the shard is down to
+/// its rent reserve. The house vault keeps its rent reserve and liabilities.
+///
+/// Like `pay_seat`, seats staked from a balance account are refunded into it.
+///
+/// Returns what was actually paid; any shortfall (neither account can cover it)
+/// is reported through `EntryFeeRefundShortfallEvent`.
+fn refund_entry_fee<'info>(
+    amount: u64,
+    game_id: [u8; 32],
+    source: &mut FeeRefundSource<'_, 'info>,
+    staked_from_balance: bool,
+    wallet: &AccountInfo<'info>,
+    balance: &mut Option<Account<'info, PlayerBalance>>,
+) -> Result<u64> {
+    let rent = Rent::get()?;
+    let shard_info = source.fee_shard.to_account_info();
+    let shard_reserve = rent.minimum_balance(shard_info.data_len());
+    let from_shard = amount.min(shard_info.lamports().saturating_sub(shard_reserve));
+
+    let house_vault_sol = source.house_vault_sol;
+    let house_reserve = rent.minimum_balance(house_vault_sol.data_len());
+    let from_house = (amount - from_shard).min(
+        source
+            .house_vault
+            .withdrawable(house_vault_sol.lamports(), house_reserve),
+    );
+    let paid = from_shard + from_house;
+
+    if paid > 0 {
+        let recipient = if staked_from_balance {
+            let balance = balance.as_mut().ok_or(RpsError::MissingPlayerBalance)?;
+            balance.amount = balance
+                .amount
+                .checked_add(paid)
+                .ok_or(RpsError::MathOverflow)?;
+            balance.to_account_info()
+        } else {
+            wallet.clone()
+        };
+        if from_shard > 0 {
+            shard_info.sub_lamports(from_shard)?;
+            recipient.add_lamports(from_shard)?;
+        }
+        pay_from_vault(
+            from_house,
+            house_vault_sol,
+            &recipient,
+            source.system_program,
+            source.house_vault_signer,
+        )?;
+    }
+    source.fee_shard.stats.record_entry_fee_refund(paid)?;
+
+    if paid < amount {
+        emit!(EntryFeeRefundShortfallEvent {
+            game_id,
+            player: wallet.key(),
+            owed: amount,
+            paid,
+        });
+    }
+    Ok(paid)
+}
+
+/// Moves a seat's bet and entry fee out of the player's balance account.
+fn stake_from_balance<'info>(
+    balance: &mut Account<'info, PlayerBalance>,
+    bet_amount: u64,
+    entry_fee: u64,
+    game_vault: &AccountInfo<'info>,
+    fee_shard: &AccountInfo<'info>,
+) -> Result<()> {
+    let total = bet_amount
+        .checked_add(entry_fee)
+        .ok_or(RpsError::MathOverflow)?;
+    balance.amount = balance
+        .amount
+        .checked_sub(total)
+        .ok_or(RpsError::InsufficientBalance)?;
+
+    let balance_info = balance.to_account_info();
+    balance_info.sub_lamports(bet_amount)?;
+    game_vault.add_lamports(bet_amount)?;
+    balance_info.sub_lamports(entry_fee)?;
+    fee_shard.add_lamports(entry_fee)?;
+    Ok(())
+}
+
+/// Pays a seat's share out of `game_vault`: into the player's balance account if the
+/// seat was staked from it, else to the wallet.
+fn pay_seat<'info>(
+    amount: u64,
+    staked_from_balance: bool,
+    wallet: &AccountInfo<'info>,
+    balance: &mut Option<Account<'info, PlayerBalance>>,
+    game_vault: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    if !staked_from_balance {
+        return pay_from_vault(amount, game_vault, wallet, system_program, signer_seeds);
+    }
+
+    let balance = balance.as_mut().ok_or(RpsError::MissingPlayerBalance)?;
+    pay_from_vault(
+        amount,
+        game_vault,
+        &balance.to_account_info(),
+        system_program,
+        signer_seeds,
+    )?;
+    balance.amount = balance
+        .amount
+        .checked_add(amount)
+        .ok_or(RpsError::MathOverflow)?;
+    Ok(())
+}
+
+/// Referrer recorded in a player's `["referral", player]` PDA, or the default key
+/// if the player never registered one (the PDA is then an empty system account).
+fn registered_referrer(referral: &AccountInfo) -> Result<Pubkey> {
+    if referral.owner != &crate::ID || referral.data_is_empty() {
+        return Ok(Pubkey::default());
+    }
+    let referral = Referral::try_deserialize(&mut &referral.data.borrow()[..])?;
+    Ok(referral.referrer)
+}
+
+/// Moves a referral reward out of `game_vault` into the referrer's rewards account.
+fn credit_referrer<'info>(
+    amount: u64,
+    rewards: &mut Option<Account<'info, ReferralRewards>>,
+    game_vault: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let rewards = rewards.as_mut().ok_or(RpsError::MissingReferrerRewards)?;
+    pay_from_vault(
+        amount,
+        game_vault,
+        &rewards.to_account_info(),
+        system_program,
+        signer_seeds,
+    )?;
+    rewards.claimable = rewards
+        .claimable
+        .checked_add(amount)
+        .ok_or(RpsError::MathOverflow)?;
+    rewards.total_earned = rewards
+        .total_earned
+        .checked_add(amount)
+        .ok_or(RpsError::MathOverflow)?;
+    Ok(())
+}
+
+/// Admin sets hold 1..=MAX_ADMINS distinct, non-default keys and a reachable threshold.
+fn validate_admin_set(
+    admins: &[Pubkey; MAX_ADMINS],
+    admin_count: u8,
+    threshold: u8,
+) -> Result<()> {
+    let count = admin_count as usize;
+    require!(count > 0 && count <= MAX_ADMINS, RpsError::InvalidAdminSet);
+    require!(threshold > 0 && threshold <= admin_count, RpsError::InvalidAdminSet);
+
+    for (i, admin) in admins[..count].iter().enumerate() {
+        require!(*admin != Pubkey::default(), RpsError::InvalidAdminSet);
+        require!(!admins[..i].contains(admin), RpsError::InvalidAdminSet);
+    }
+    require!(
+        admins[count..].iter().all(|a| *a == Pubkey::default()),
+        RpsError::InvalidAdminSet
+    );
+    Ok(())
+}
+
+/// 0 = Rock, 1 = Paper, 2 = Scissors
+fn round_winner(m1: u8, m2: u8) -> RoundResult {
+    use RoundResult::*;
+    if m1 == m2 {
+        return Draw;
+    }
+    match (m1, m2) {
+        (0, 2) | (1, 0) | (2, 1) => Player1Win,
+        (2, 0) | (0, 1) | (1, 2) => Player2Win,
+        _ => Draw,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RoundResult {
+    Player1Win,
+    Player2Win,
+    Draw,
+}
+
+// ---------- Accounts & State ----------
+
+#[derive(Accounts)]
+pub struct WithdrawHouseFunds<'info> {
+    pub treasurer: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", treasurer.key().as_ref()],
+        bump = treasurer_role.bump,
+        constraint = treasurer_role.has(Role::Treasurer) @ RpsError::MissingRole
+    )]
+    pub treasurer_role: Account<'info, RoleAssignment>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// CHECK: Receives the proposal rent. Address verified against proposal.proposer.
+    #[account(mut, address = proposal.proposer @ RpsError::InvalidProposer)]
+    pub proposer: AccountInfo<'info>,
+
+    /// CHECK: Withdrawal destination. Address verified against the proposal in the handler.
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump,
+    /// and we only use it as a lamport holder (no deserialization).
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPayees<'info> {
+    pub treasurer: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", treasurer.key().as_ref()],
+        bump = treasurer_role.bump,
+        constraint = treasurer_role.has(Role::Treasurer) @ RpsError::MissingRole
+    )]
+    pub treasurer_role: Account<'info, RoleAssignment>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// CHECK: Receives the proposal rent. Address verified against proposal.proposer.
+    #[account(mut, address = proposal.proposer @ RpsError::InvalidProposer)]
+    pub proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageLiabilities<'info> {
+    pub treasurer: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", treasurer.key().as_ref()],
+        bump = treasurer_role.bump,
+        constraint = treasurer_role.has(Role::Treasurer) @ RpsError::MissingRole
+    )]
+    pub treasurer_role: Account<'info, RoleAssignment>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump,
+    /// and we only read its balance.
+    #[account(
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeHouseFunds<'info> {
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump,
+    /// and we only use it as a lamport holder (no deserialization).
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+    // remaining_accounts: writable payee accounts, in `house_vault.payee_split` order
+}
+
+#[derive(Accounts)]
+#[instruction(shard_index: u8)]
+pub struct InitFeeShard<'info> {
+    #[account(
+        mut,
+        address = house_vault.admin @ RpsError::Unauthorized
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = FeeShard::SPACE,
+        seeds = [b"fee_shard", [shard_index].as_ref()],
+        bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepFeeShards<'info> {
+    #[account(address = house_vault.admin @ RpsError::Unauthorized)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump,
+    /// and we only credit lamports to it.
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+    // remaining_accounts: writable `FeeShard` PDAs to sweep
+}
+
+#[derive(Accounts)]
+#[instruction(epoch: u64)]
+pub struct RecordEpochReport<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump,
+    /// and we only read its balance.
+    #[account(
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = EpochReport::SPACE,
+        seeds = [b"epoch_report", epoch.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub epoch_report: Account<'info, EpochReport>,
+
+    pub system_program: Program<'info, System>,
+    // remaining_accounts: all `FeeShard` PDAs
+}
+
+#[derive(Accounts)]
+pub struct MigrateHouseVaultSol<'info> {
+    #[account(address = house_vault.admin @ RpsError::Unauthorized)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: Legacy system-owned house SOL vault. Address is verified by seeds and bump, and
+    /// the only operation is re-assigning its owner to this program.
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        owner = system_program::ID
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateHouseVault<'info> {
+    /// Admin recorded in the legacy account (checked in the handler)
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// CHECK: Legacy `HouseVault` that no longer deserializes; address is verified by seeds,
+    /// and the discriminator and stored admin in the handler.
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump,
+        owner = crate::ID
+    )]
+    pub house_vault: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGame<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Legacy `Game` that no longer deserializes; the handler checks its PDA
+    /// address against the seeds stored in it, and its discriminator.
+    #[account(mut, owner = crate::ID)]
+    pub game: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StartRound<'info> {
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// Must be a player or a session key authorized for this game or by the player's
+    /// wallet session (checked in the handler)
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+        constraint = house_vault.key() == game.house_vault @ RpsError::InvalidHouseWallet
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// Wallet session of the player `caller` is acting for, if it is that session's key.
+    #[account(
+        seeds = [b"session", wallet_session.owner.as_ref()],
+        bump = wallet_session.bump
+    )]
+    pub wallet_session: Option<Account<'info, WalletSession>>,
+}
+
+
+#[derive(Accounts)]
+pub struct ResolveCommitTimeout<'info> {
+    /// Anyone can call this (mediator, player, random user).
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+}
+
+#[derive(Accounts)]
+pub struct ForfeitGame<'info> {
+    /// Must be one of the players or their session key
+    #[account(
+        constraint =
+            caller.key() == game.player1 ||
+            caller.key() == game.player2 ||
+            caller.key() == game.session_p1 ||
+            caller.key() == game.session_p2
+            @ RpsError::NotAPlayer
+    )]
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+}
+
+#[derive(Accounts)]
+pub struct AuditGame<'info> {
+    #[account(
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: Game vault PDA, only its balance and owner are read. Address enforced via
+    /// seeds + bump; may already be closed.
+    #[account(
+        seeds = [b"game_vault", &game.game_id],
+        bump
+    )]
+    pub game_vault: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelGame<'info> {
+    /// Anyone can call cancel_game
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: Player 1 account to receive refund
+    #[account(
+        mut,
+        constraint = player1.key() == game.player1 @ RpsError::InvalidPlayerAccount
+    )]
+    pub player1: AccountInfo<'info>,
+
+    /// CHECK: Player 2 account to receive refund
+    #[account(
+        mut,
+        constraint = player2.key() == game.player2 @ RpsError::InvalidPlayerAccount
+    )]
+    pub player2: AccountInfo<'info>,
+
+    /// Game vault PDA that holds the bet
+    /// CHECK: This is a PDA that holds SOL, not an Anchor account
+    #[account(
+        mut,
+        seeds = [b"game_vault", &game.game_id],
+        bump,
+        constraint = is_vault_owner(game_vault.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub game_vault: AccountInfo<'info>,
+
+    /// House fee shard holding this game's entry fees; pays the fee refunds and
+    /// records them.
+    #[account(
+        mut,
+        seeds = [b"fee_shard", [fee_shard_index(&game.game_id)].as_ref()],
+        bump = fee_shard.bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump;
+    /// it covers entry fee refunds already swept out of `fee_shard`.
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    /// Player1's balance account; required if player1 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player1.as_ref()],
+        bump = player1_balance.bump
+    )]
+    pub player1_balance: Option<Account<'info, PlayerBalance>>,
+
+    /// Player2's balance account; required if player2 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player2.as_ref()],
+        bump = player2_balance.bump
+    )]
+    pub player2_balance: Option<Account<'info, PlayerBalance>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(referrer: Pubkey)]
+pub struct RegisterReferrer<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        init,
+        payer = player,
+        space = Referral::SPACE,
+        seeds = [b"referral", player.key().as_ref()],
+        bump
+    )]
+    pub referral: Account<'info, Referral>,
+
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = ReferralRewards::SPACE,
+        seeds = [b"referral_rewards", referrer.as_ref()],
+        bump
+    )]
+    pub referrer_rewards: Account<'info, ReferralRewards>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterIntegrator<'info> {
+    #[account(mut)]
+    pub integrator: Signer<'info>,
+
+    #[account(
+        init,
+        payer = integrator,
+        space = IntegratorRewards::SPACE,
+        seeds = [b"integrator_rewards", integrator.key().as_ref()],
+        bump
+    )]
+    pub integrator_rewards: Account<'info, IntegratorRewards>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimIntegratorRewards<'info> {
+    #[account(mut)]
+    pub integrator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"integrator_rewards", integrator.key().as_ref()],
+        bump = integrator_rewards.bump
+    )]
+    pub integrator_rewards: Account<'info, IntegratorRewards>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"referral_rewards", referrer.key().as_ref()],
+        bump = referrer_rewards.bump
+    )]
+    pub referrer_rewards: Account<'info, ReferralRewards>,
+}
+
+#[derive(Accounts)]
+pub struct DepositBalance<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = PlayerBalance::SPACE,
+        seeds = [b"balance", player.key().as_ref()],
+        bump
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBalance<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"balance", player.key().as_ref()],
+        bump = player_balance.bump
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+}
+
+#[derive(Accounts)]
+pub struct ManageSessionSigner<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump,
+        constraint = player.key() == game.player1 || player.key() == game.player2
+            @ RpsError::NotAPlayer
+    )]
+    pub game: Account<'info, Game>,
+}
+
+#[derive(Accounts)]
+pub struct OpenWalletSession<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = WalletSession::SPACE,
+        seeds = [b"session", owner.key().as_ref()],
+        bump
+    )]
+    pub wallet_session: Account<'info, WalletSession>,
+
+    /// CHECK: The ephemeral session key, only receives the optional top-up.
+    /// Address verified against the `session_key` argument in the handler.
+    #[account(mut)]
+    pub session_signer: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpWalletSession<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"session", owner.key().as_ref()],
+        bump = wallet_session.bump
+    )]
+    pub wallet_session: Account<'info, WalletSession>,
+
+    /// CHECK: Receives the top-up. Address verified against wallet_session.session_key.
+    #[account(
+        mut,
+        address = wallet_session.session_key @ RpsError::InvalidSession
+    )]
+    pub session_signer: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseWalletSession<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"session", owner.key().as_ref()],
+        bump = wallet_session.bump
+    )]
+    pub wallet_session: Account<'info, WalletSession>,
+
+    /// The session key, if it co-signs to have its leftover lamports refunded.
+    #[account(
+        mut,
+        address = wallet_session.session_key @ RpsError::InvalidSession
+    )]
+    pub session_signer: Option<Signer<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminForceRefund<'info> {
+    /// Must hold the refunder role
+    pub refunder: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", refunder.key().as_ref()],
+        bump = refunder_role.bump,
+        constraint = refunder_role.has(Role::Refunder) @ RpsError::MissingRole
+    )]
+    pub refunder_role: Account<'info, RoleAssignment>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// CHECK: Receives the proposal rent. Address verified against proposal.proposer.
+    #[account(mut, address = proposal.proposer @ RpsError::InvalidProposer)]
+    pub proposer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        close = player1,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: Player1 receives the refund. Address verified against game.player1.
+    #[account(
+        mut,
+        address = game.player1 @ RpsError::InvalidPlayerAccount
+    )]
+    pub player1: AccountInfo<'info>,
+
+    /// CHECK: Player2 receives their refund. Address verified against game.player2; may be
+    /// omitted when player2 never joined.
+    #[account(
+        mut,
+        address = game.player2 @ RpsError::InvalidPlayerAccount
+    )]
+    pub player2: Option<AccountInfo<'info>>,
+
+    /// CHECK: Game vault PDA holding the bet. Address enforced via seeds + bump.
+    #[account(
+        mut,
+        seeds = [b"game_vault", &game.game_id],
+        bump,
+        constraint = is_vault_owner(game_vault.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub game_vault: UncheckedAccount<'info>,
+
+    /// House fee shard holding this game's entry fees; pays the fee refunds and
+    /// records them.
+    #[account(
+        mut,
+        seeds = [b"fee_shard", [fee_shard_index(&game.game_id)].as_ref()],
+        bump = fee_shard.bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump;
+    /// it covers entry fee refunds already swept out of `fee_shard`.
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    /// Player1's balance account; required if player1 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player1.as_ref()],
+        bump = player1_balance.bump
+    )]
+    pub player1_balance: Option<Account<'info, PlayerBalance>>,
+
+    /// Player2's balance account; required if player2 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player2.as_ref()],
+        bump = player2_balance.bump
+    )]
+    pub player2_balance: Option<Account<'info, PlayerBalance>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelGameIfTimedOut<'info> {
+    #[account(mut)]
+    pub player1: Signer<'info>,
+
+    #[account(
+        mut,
+        close = player1,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: This is the PDA vault holding the game pot. Address is enforced via seeds and bump,
+    /// and we only move lamports from it (no data layout is assumed).
+    #[account(
+        mut,
+        seeds = [b"game_vault", &game.game_id],
+        bump,
+        constraint = is_vault_owner(game_vault.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub game_vault: UncheckedAccount<'info>,
+
+    /// House fee shard holding this game's entry fees; pays the fee refunds and
+    /// records them.
+    #[account(
+        mut,
+        seeds = [b"fee_shard", [fee_shard_index(&game.game_id)].as_ref()],
+        bump = fee_shard.bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: This is a PDA vault for house funds. Its address is verified by seeds and bump;
+    /// it covers entry fee refunds already swept out of `fee_shard`.
+    #[account(
+        mut,
+        seeds = [b"house_vault_sol"],
+        bump,
+        constraint = is_vault_owner(house_vault_sol.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    /// Player1's balance account; required if player1 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player1.as_ref()],
+        bump = player1_balance.bump
+    )]
+    pub player1_balance: Option<Account<'info, PlayerBalance>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyExit<'info> {
+    /// Anyone can trigger the escape hatch once it unlocks
+    pub caller: Signer<'info>,
+
+    /// Read for how long the house has been paused
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        close = player1,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: Player1 receives their deposit and all rent. Address verified against game.player1.
+    #[account(
+        mut,
+        address = game.player1 @ RpsError::InvalidPlayerAccount
+    )]
+    pub player1: AccountInfo<'info>,
+
+    /// CHECK: Player2 receives their deposit. Address verified against game.player2; may be
+    /// omitted when player2 never joined.
+    #[account(
+        mut,
+        address = game.player2 @ RpsError::InvalidPlayerAccount
+    )]
+    pub player2: Option<AccountInfo<'info>>,
+
+    /// CHECK: This is the PDA vault holding the game pot. Address is enforced via seeds and bump,
+    /// and we only move lamports from it (no data layout is assumed).
+    #[account(
+        mut,
+        seeds = [b"game_vault", &game.game_id],
+        bump,
+        constraint = is_vault_owner(game_vault.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub game_vault: UncheckedAccount<'info>,
+
+    /// House fee shard for this game; records the refund.
+    #[account(
+        mut,
+        seeds = [b"fee_shard", [fee_shard_index(&game.game_id)].as_ref()],
+        bump = fee_shard.bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    /// Player1's balance account; required if player1 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player1.as_ref()],
+        bump = player1_balance.bump
+    )]
+    pub player1_balance: Option<Account<'info, PlayerBalance>>,
+
+    /// Player2's balance account; required if player2 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player2.as_ref()],
+        bump = player2_balance.bump
+    )]
+    pub player2_balance: Option<Account<'info, PlayerBalance>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetHouseConfig<'info> {
+    #[account(address = house_vault.admin @ RpsError::Unauthorized)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+}
+
+#[derive(Accounts)]
+pub struct SetHouseFee<'info> {
+    pub fee_manager: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", fee_manager.key().as_ref()],
+        bump = fee_manager_role.bump,
+        constraint = fee_manager_role.has(Role::FeeManager) @ RpsError::MissingRole
+    )]
+    pub fee_manager_role: Account<'info, RoleAssignment>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// CHECK: Receives the proposal rent. Address verified against proposal.proposer.
+    #[account(mut, address = proposal.proposer @ RpsError::InvalidProposer)]
+    pub proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(member: Pubkey)]
+pub struct GrantRole<'info> {
+    #[account(
+        mut,
+        address = house_vault.admin @ RpsError::Unauthorized
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = RoleAssignment::SPACE,
+        seeds = [b"role", member.as_ref()],
+        bump
+    )]
+    pub role_assignment: Account<'info, RoleAssignment>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeRole<'info> {
+    #[account(address = house_vault.admin @ RpsError::Unauthorized)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        seeds = [b"role", role_assignment.member.as_ref()],
+        bump = role_assignment.bump
+    )]
+    pub role_assignment: Account<'info, RoleAssignment>,
+}
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    /// Super-admin or a holder of the pauser role
+    #[account(
+        constraint = authority.key() == house_vault.admin
+            || authority_role.as_ref().is_some_and(|r| r.has(Role::Pauser))
+            @ RpsError::MissingRole
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", authority.key().as_ref()],
+        bump = authority_role.bump
+    )]
+    pub authority_role: Option<Account<'info, RoleAssignment>>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(address = house_vault.admin @ RpsError::Unauthorized)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(
+        address = house_vault.pending_admin @ RpsError::Unauthorized,
+        constraint = new_admin.key() != Pubkey::default() @ RpsError::Unauthorized
+    )]
+    pub new_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+}
+
+#[derive(Accounts)]
+pub struct ManageFees<'info> {
+    pub fee_manager: Signer<'info>,
+
+    #[account(
+        seeds = [b"role", fee_manager.key().as_ref()],
+        bump = fee_manager_role.bump,
+        constraint = fee_manager_role.has(Role::FeeManager) @ RpsError::MissingRole
+    )]
+    pub fee_manager_role: Account<'info, RoleAssignment>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAdminSet<'info> {
+    #[account(
+        constraint = house_vault.admin_index(&admin.key()).is_some() @ RpsError::Unauthorized
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// CHECK: Receives the proposal rent. Address verified against proposal.proposer.
+    #[account(mut, address = proposal.proposer @ RpsError::InvalidProposer)]
+    pub proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::SPACE,
+        seeds = [b"proposal", house_vault.proposal_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// Proposer's role account; required for role-gated actions.
+    #[account(
+        seeds = [b"role", proposer.key().as_ref()],
+        bump = proposer_role.bump
+    )]
+    pub proposer_role: Option<Account<'info, RoleAssignment>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveProposal<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+}
+
+#[derive(Accounts)]
+pub struct CancelProposal<'info> {
+    #[account(mut, address = proposal.proposer @ RpsError::InvalidProposer)]
+    pub proposer: Signer<'info>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GameStatus {
+    WaitingForPlayer2 = 0,
+    Active = 1,
+    Finished = 2,
+    Cancelled = 3,
+    Settled = 4, // payouts done, cannot be settled again
+}
+
+#[account]
+pub struct HouseVault {
+    pub bump: u8,
+    pub admin: Pubkey,      // super-admin (roles, fee shards, vault migration)
+    pub house_fee_bps: u16, // current global fee configuration
+    pub pending_admin: Pubkey, // nominee awaiting `accept_admin`, default if none
+
+    // M-of-N admin set approving fee changes, withdrawals and force refunds
+    pub admins: [Pubkey; MAX_ADMINS], // first `admin_count` entries are in use
+    pub admin_count: u8,
+    pub threshold: u8,
+    pub admin_set_version: u32, // bumped on every admin set change
+    pub proposal_count: u64,    // next proposal id
+
+    pub paused_ops: u8, // tripped `PAUSE_*` circuit breakers
+    pub emergency_exit_delay_slots: u64, // snapshotted into new games
+
+    // timelocked fee change; replaces house_fee_bps once the activation slot is reached
+    pub pending_house_fee_bps: u16,
+    pub pending_fee_activation_slot: u64, // 0 if nothing is pending
+
+    // bet-size rake tiers and absolute caps, timelocked like the house fee
+    pub rake_schedule: RakeSchedule,
+    pub pending_rake_schedule: RakeSchedule,
+    pub pending_rake_activation_slot: u64, // 0 if nothing is pending
+
+    pub referral_share_bps: u16, // share of the rake credited to referrers
+    pub max_integrator_fee_bps: u16, // cap on per-game integrator fees
+    pub payee_split: PayeeSplit,     // recipients of `distribute_house_funds`
+
+    // funds withdrawals must leave behind, and the per-epoch outflow limit
+    pub liabilities: u64,              // committed by treasurers, on top of rent
+    pub withdrawal_cap_per_epoch: u64, // 0 means uncapped
+    pub withdrawal_epoch: u64,         // epoch `withdrawn_this_epoch` refers to
+    pub withdrawn_this_epoch: u64,
+
+    pub stats: HouseStats, // withdrawals; see `FeeShard` for the rest
+
+    pub fee_refund_policy: FeeRefundPolicy, // snapshotted into new games
+
+    pub paused_since_slot: u64, // when `paused_ops` last went non-zero, 0 while unpaused
+}
+
+impl HouseVault {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // admin
+        + 2                    // house_fee_bps
+        + 32                   // pending_admin
+        + 32 * MAX_ADMINS      // admins
+        + 1                    // admin_count
+        + 1                    // threshold
+        + 4                    // admin_set_version
+        + 8                    // proposal_count
+        + 1                    // paused_ops
+        + 8                    // emergency_exit_delay_slots
+        + 2                    // pending_house_fee_bps
+        + 8                    // pending_fee_activation_slot
+        + RakeSchedule::SPACE  // rake_schedule
+        + RakeSchedule::SPACE  // pending_rake_schedule
+        + 8                    // pending_rake_activation_slot
+        + 2                    // referral_share_bps
+        + 2                    // max_integrator_fee_bps
+        + PayeeSplit::SPACE    // payee_split
+        + 8                    // liabilities
+        + 8                    // withdrawal_cap_per_epoch
+        + 8                    // withdrawal_epoch
+        + 8                    // withdrawn_this_epoch
+        + HouseStats::SPACE    // stats
+        + FeeRefundPolicy::SPACE // fee_refund_policy
+        + 8;                   // paused_since_slot
+
+    /// Sets up the fields a `migrate_house_vault` left zeroed, the same way
+    /// `init_house_vault` would.
+    pub fn upgrade_legacy(&mut self) {
+        self.admins[0] = self.admin;
+        self.admin_count = 1;
+        self.threshold = 1;
+        self.emergency_exit_delay_slots = DEFAULT_EMERGENCY_EXIT_DELAY_SLOTS;
+    }
+
+    /// Hands the super-admin role to `nominee` if they are the pending nominee,
+    /// returning the previous admin.
+    pub fn accept_pending_admin(&mut self, nominee: &Pubkey) -> Result<Pubkey> {
+        require!(
+            *nominee != Pubkey::default() && *nominee == self.pending_admin,
+            RpsError::Unauthorized
+        );
+        let previous_admin = self.admin;
+        self.admin = *nominee;
+        self.pending_admin = Pubkey::default();
+        Ok(previous_admin)
+    }
+
+    pub fn admin_index(&self, key: &Pubkey) -> Option<usize> {
+        self.admins[..self.admin_count as usize]
+            .iter()
+            .position(|admin| admin == key)
+    }
+
+    /// Lamports of a `balance` that may leave `house_vault_sol` without touching
+    /// its rent-exempt `reserve` or committed liabilities.
+    pub fn withdrawable(&self, balance: u64, reserve: u64) -> u64 {
+        balance
+            .saturating_sub(reserve)
+            .saturating_sub(self.liabilities)
+    }
+
+    /// What the per-epoch withdrawal cap still allows in `epoch`.
+    pub fn remaining_withdrawal_cap(&self, epoch: u64) -> u64 {
+        if self.withdrawal_cap_per_epoch == 0 {
+            return u64::MAX;
+        }
+        let withdrawn = if self.withdrawal_epoch == epoch {
+            self.withdrawn_this_epoch
+        } else {
+            0
+        };
+        self.withdrawal_cap_per_epoch.saturating_sub(withdrawn)
+    }
+
+    /// Counts `amount` against the withdrawal cap of `epoch` and the
+    /// `withdrawals` counter.
+    pub fn record_withdrawal(&mut self, epoch: u64, amount: u64) -> Result<()> {
+        if self.withdrawal_epoch != epoch {
+            self.withdrawal_epoch = epoch;
+            self.withdrawn_this_epoch = 0;
+        }
+        self.withdrawn_this_epoch = self
+            .withdrawn_this_epoch
+            .checked_add(amount)
+            .ok_or(RpsError::MathOverflow)?;
+        self.stats.withdrawals = self
+            .stats
+            .withdrawals
+            .checked_add(amount)
+            .ok_or(RpsError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Fee new games are charged at `slot`, taking a matured pending change into account.
+    /// Readers use this rather than `house_fee_bps`, which is only updated lazily.
+    pub fn effective_house_fee_bps(&self, slot: u64) -> u16 {
+        if self.pending_fee_activation_slot != 0 && slot >= self.pending_fee_activation_slot {
+            self.pending_house_fee_bps
+        } else {
+            self.house_fee_bps
+        }
+    }
+
+    /// Folds a matured pending fee change into `house_fee_bps`.
+    pub fn apply_pending_house_fee(&mut self, slot: u64) {
+        if self.pending_fee_activation_slot != 0 && slot >= self.pending_fee_activation_slot {
+            self.house_fee_bps = self.pending_house_fee_bps;
+            self.pending_house_fee_bps = 0;
+            self.pending_fee_activation_slot = 0;
+        }
+    }
+
+    /// Rake schedule in force at `slot`; see `effective_house_fee_bps`.
+    pub fn effective_rake_schedule(&self, slot: u64) -> RakeSchedule {
+        if self.pending_rake_activation_slot != 0 && slot >= self.pending_rake_activation_slot {
+            self.pending_rake_schedule
+        } else {
+            self.rake_schedule
+        }
+    }
+
+    /// Folds a matured pending rake schedule into `rake_schedule`.
+    pub fn apply_pending_rake_schedule(&mut self, slot: u64) {
+        if self.pending_rake_activation_slot != 0 && slot >= self.pending_rake_activation_slot {
+            self.rake_schedule = self.pending_rake_schedule;
+            self.pending_rake_schedule = RakeSchedule::default();
+            self.pending_rake_activation_slot = 0;
+        }
+    }
+}
+
+/// Bets of at least `min_bet` lamports are raked at `fee_bps`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RakeTier {
+    pub min_bet: u64,
+    pub fee_bps: u16,
+}
+
+/// Bet-size rake tiers on top of the base `house_fee_bps`, plus absolute caps.
+///
+/// Bets below the first tier pay the base fee. `max_fee == 0` means uncapped.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RakeSchedule {
+    pub tiers: [RakeTier; MAX_RAKE_TIERS], // first `tier_count` in use, ascending min_bet
+    pub tier_count: u8,
+    pub min_fee: u64,
+    pub max_fee: u64,
+}
+
+impl RakeSchedule {
+    pub const SPACE: usize = (8 + 2) * MAX_RAKE_TIERS // tiers
+        + 1                                           // tier_count
+        + 8                                           // min_fee
+        + 8;                                          // max_fee
+
+    pub fn validate(&self) -> Result<()> {
+        let count = self.tier_count as usize;
+        require!(count <= MAX_RAKE_TIERS, RpsError::InvalidRakeSchedule);
+
+        let tiers = &self.tiers[..count];
+        for (i, tier) in tiers.iter().enumerate() {
+            require!(tier.fee_bps <= MAX_HOUSE_FEE_BPS, RpsError::InvalidHouseFee);
+            if i > 0 {
+                require!(tier.min_bet > tiers[i - 1].min_bet, RpsError::InvalidRakeSchedule);
+            }
+        }
+        require!(
+            self.tiers[count..].iter().all(|t| *t == RakeTier::default()),
+            RpsError::InvalidRakeSchedule
+        );
+        require!(
+            self.max_fee == 0 || self.min_fee <= self.max_fee,
+            RpsError::InvalidRakeSchedule
+        );
+        require!(self.min_fee <= MAX_MIN_HOUSE_FEE, RpsError::InvalidRakeSchedule);
+        Ok(())
+    }
+
+    /// Rate for a bet: the highest tier the bet reaches, else `base_fee_bps`.
+    pub fn fee_bps_for(&self, bet_amount: u64, base_fee_bps: u16) -> u16 {
+        self.tiers[..self.tier_count as usize]
+            .iter()
+            .rev()
+            .find(|tier| bet_amount >= tier.min_bet)
+            .map_or(base_fee_bps, |tier| tier.fee_bps)
+    }
+}
+
+/// Receives `share_bps` of every house revenue distribution.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Payee {
+    pub recipient: Pubkey,
+    pub share_bps: u16,
+}
+
+/// House revenue split (e.g. treasury, development, community pool).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PayeeSplit {
+    pub payees: [Payee; MAX_PAYEES], // first `payee_count` in use
+    pub payee_count: u8,
+}
+
+impl PayeeSplit {
+    pub const SPACE: usize = (32 + 2) * MAX_PAYEES // payees
+        + 1;                                       // payee_count
+
+    pub fn validate(&self) -> Result<()> {
+        let count = self.payee_count as usize;
+        require!(count > 0 && count <= MAX_PAYEES, RpsError::InvalidPayees);
+
+        let payees = &self.payees[..count];
+        let mut total_bps: u64 = 0;
+        for (i, payee) in payees.iter().enumerate() {
+            require!(
+                payee.recipient != Pubkey::default() && payee.share_bps > 0,
+                RpsError::InvalidPayees
+            );
+            require!(
+                !payees[..i].iter().any(|p| p.recipient == payee.recipient),
+                RpsError::InvalidPayees
+            );
+            total_bps += payee.share_bps as u64;
+        }
+        require!(total_bps == BPS_DENOMINATOR, RpsError::InvalidPayees);
+        require!(
+            self.payees[count..].iter().all(|p| *p == Payee::default()),
+            RpsError::InvalidPayees
+        );
+        Ok(())
+    }
+}
+
+/// Why a game was cancelled, selecting its entry fee refund rate.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    NoOpponent,        // nobody joined before the timeout
+    AbortedBeforePlay, // `cancel_game` before any round was played
+    AbortedMidGame,    // `cancel_game` after at least one round
+}
+
+/// Share of each seat's entry fee returned per cancellation reason
+/// (10_000 = full refund, 0 = none).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeRefundPolicy {
+    pub no_opponent_bps: u16,
+    pub aborted_before_play_bps: u16,
+    pub aborted_mid_game_bps: u16,
+}
+
+impl FeeRefundPolicy {
+    pub const SPACE: usize = 2 * 3;
+
+    pub fn validate(&self) -> Result<()> {
+        let max = BPS_DENOMINATOR as u16;
+        require!(
+            self.no_opponent_bps <= max
+                && self.aborted_before_play_bps <= max
+                && self.aborted_mid_game_bps <= max,
+            RpsError::InvalidFeeRefundPolicy
+        );
+        Ok(())
+    }
+
+    /// Part of an `entry_fee` returned for a game cancelled because of `reason`.
+    pub fn refund_for(&self, entry_fee: u64, reason: CancelReason) -> Result<u64> {
+        let bps = match reason {
+            CancelReason::NoOpponent => self.no_opponent_bps,
+            CancelReason::AbortedBeforePlay => self.aborted_before_play_bps,
+            CancelReason::AbortedMidGame => self.aborted_mid_game_bps,
+        };
+        let refund = (entry_fee as u128)
+            .checked_mul(bps as u128)
+            .ok_or(RpsError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+        u64::try_from(refund).map_err(|_| error!(RpsError::MathOverflow))
+    }
+}
+
+/// Score of a state channel match after `seq` off-chain updates, co-signed by both
+/// players.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelState {
+    pub seq: u64,
+    pub rounds_played: u8,
+    pub player1_wins: u8,
+    pub player2_wins: u8,
+}
+
+impl ChannelState {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            (self.rounds_played as usize) <= MAX_ROUNDS
+                && self.player1_wins <= 3
+                && self.player2_wins <= 3
+                && self.player1_wins as u16 + self.player2_wins as u16 <= self.rounds_played as u16,
+            RpsError::InvalidChannelState
+        );
+        Ok(())
+    }
+
+    /// Whether the match is decided, matching the on-chain end conditions.
+    pub fn is_final(&self) -> bool {
+        self.player1_wins >= 3
+            || self.player2_wins >= 3
+            || self.rounds_played as usize >= MAX_ROUNDS
+    }
+}
+
+/// Operational permissions delegated by the super-admin.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Role {
+    FeeManager = 0, // proposes / executes house fee changes
+    Treasurer = 1,  // proposes / executes house withdrawals and payee changes
+    Pauser = 2,     // pauses / unpauses the house
+    Refunder = 3,   // proposes / executes force refunds
+}
+
+impl Role {
+    pub fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// Roles held by one wallet. PDA: ["role", member].
+#[account]
+pub struct RoleAssignment {
+    pub bump: u8,
+    pub member: Pubkey,
+    pub roles: u8, // bitmask of `Role::mask()`
+}
+
+impl RoleAssignment {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // member
+        + 1;                   // roles
+
+    pub fn has(&self, role: Role) -> bool {
+        self.roles & role.mask() != 0
+    }
+}
+
+/// Privileged house operation awaiting admin approvals.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ProposalAction {
+    SetHouseFee {
+        new_fee_bps: u16,
+    },
+    SetRakeSchedule {
+        schedule: RakeSchedule,
+    },
+    WithdrawHouseFunds {
+        amount: u64,
+        recipient: Pubkey,
+    },
+    AdminForceRefund {
+        game_id: [u8; 32],
+        refund_entry_fees: bool, // also return entry fees from the game's fee shard
+    },
+    UpdateAdminSet {
+        admins: [Pubkey; MAX_ADMINS],
+        admin_count: u8,
+        threshold: u8,
+    },
+    SetPayees {
+        split: PayeeSplit,
+    },
+}
+
+impl ProposalAction {
+    // enum tag + largest variant (UpdateAdminSet)
+    pub const SPACE: usize = 1 + 32 * MAX_ADMINS + 1 + 1;
+
+    /// Role needed to propose and execute this action; `None` means admin set members.
+    pub fn required_role(&self) -> Option<Role> {
+        match self {
+            ProposalAction::SetHouseFee { .. } => Some(Role::FeeManager),
+            ProposalAction::SetRakeSchedule { .. } => Some(Role::FeeManager),
+            ProposalAction::WithdrawHouseFunds { .. } => Some(Role::Treasurer),
+            ProposalAction::AdminForceRefund { .. } => Some(Role::Refunder),
+            ProposalAction::UpdateAdminSet { .. } => None,
+            ProposalAction::SetPayees { .. } => Some(Role::Treasurer),
+        }
+    }
+}
+
+/// A pending admin proposal. Executing or cancelling it closes the account,
+/// so an existing proposal is always a pending one.
+#[account]
+pub struct Proposal {
+    pub bump: u8,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+    pub admin_set_version: u32, // admin set the approvals refer to
+    pub approvals: u16,         // bitmask over HouseVault.admins indices
+    pub created_slot: u64,
+    pub expiry_slot: u64,
+}
+
+impl Proposal {
+    pub const SPACE: usize = 8      // discriminator
+        + 1                         // bump
+        + 8                         // id
+        + 32                        // proposer
+        + ProposalAction::SPACE     // action
+        + 4                         // admin_set_version
+        + 2                         // approvals
+        + 8                         // created_slot
+        + 8;                        // expiry_slot
+}
+
+/// One of `FEE_SHARD_COUNT` program-owned PDAs collecting entry fees and rake.
+/// Its lamports above the rent-exempt minimum are swept into `house_vault_sol`.
+#[account]
+pub struct FeeShard {
+    pub bump: u8,
+    pub index: u8,
+    pub stats: HouseStats, // games, volume, fees and refunds routed to this shard
+}
+
+impl FeeShard {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 1                    // index
+        + HouseStats::SPACE;   // stats
+}
+
+/// Cumulative house accounting counters (lamports unless noted).
+///
+/// Game activity is counted in the game's `FeeShard`, house outflows in `HouseVault`,
+/// so the hot paths never write the global config. Summing all of them gives the
+/// house totals recorded in an `EpochReport`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HouseStats {
+    pub games_created: u64, // count
+    pub games_settled: u64, // count
+    pub volume_wagered: u64,
+    pub entry_fees_collected: u64,
+    pub rake_collected: u64, // house share after referral rewards
+    pub bets_refunded: u64,
+    pub entry_fees_refunded: u64, // paid back out of the fee shards or house_vault_sol
+    pub withdrawals: u64,         // withdrawals and payee distributions
+}
+
+impl HouseStats {
+    pub const SPACE: usize = 8 * 8;
+
+    pub fn record_game_created(&mut self) -> Result<()> {
+        add_to_counter(&mut self.games_created, 1)
+    }
+
+    /// A player's bet going into a game vault and entry fee into the shard.
+    pub fn record_deposit(&mut self, bet_amount: u64, entry_fee: u64) -> Result<()> {
+        add_to_counter(&mut self.volume_wagered, bet_amount)?;
+        add_to_counter(&mut self.entry_fees_collected, entry_fee)
+    }
+
+    pub fn record_settlement(&mut self, rake: u64) -> Result<()> {
+        add_to_counter(&mut self.games_settled, 1)?;
+        add_to_counter(&mut self.rake_collected, rake)
+    }
+
+    pub fn record_bet_refund(&mut self, amount: u64) -> Result<()> {
+        add_to_counter(&mut self.bets_refunded, amount)
+    }
+
+    pub fn record_entry_fee_refund(&mut self, amount: u64) -> Result<()> {
+        add_to_counter(&mut self.entry_fees_refunded, amount)
+    }
+
+    /// Adds another set of counters into this one.
+    pub fn accumulate(&mut self, other: &HouseStats) -> Result<()> {
+        add_to_counter(&mut self.games_created, other.games_created)?;
+        add_to_counter(&mut self.games_settled, other.games_settled)?;
+        add_to_counter(&mut self.volume_wagered, other.volume_wagered)?;
+        add_to_counter(&mut self.entry_fees_collected, other.entry_fees_collected)?;
+        add_to_counter(&mut self.rake_collected, other.rake_collected)?;
+        add_to_counter(&mut self.bets_refunded, other.bets_refunded)?;
+        add_to_counter(&mut self.entry_fees_refunded, other.entry_fees_refunded)?;
+        add_to_counter(&mut self.withdrawals, other.withdrawals)
+    }
+}
+
+/// House totals and balances captured once per epoch. PDA: ["epoch_report", epoch].
+///
+/// Balances exclude rent-exempt minimums, so apart from unsolicited transfers
+/// `fee_shard_balance + house_vault_balance` equals
+/// `entry_fees_collected + rake_collected - entry_fees_refunded - withdrawals`.
+#[account]
+pub struct EpochReport {
+    pub bump: u8,
+    pub epoch: u64,
+    pub slot: u64, // when the snapshot was taken
+    pub stats: HouseStats,
+    pub fee_shard_balance: u64,   // across all shards
+    pub house_vault_balance: u64, // house_vault_sol
+}
+
+impl EpochReport {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 8                    // epoch
+        + 8                    // slot
+        + HouseStats::SPACE    // stats
+        + 8                    // fee_shard_balance
+        + 8;                   // house_vault_balance
+}
+
+/// A player's referrer, registered once. PDA: ["referral", player].
+#[account]
+pub struct Referral {
+    pub bump: u8,
+    pub player: Pubkey,
+    pub referrer: Pubkey,
+}
+
+impl Referral {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // player
+        + 32;                  // referrer
+}
+
+/// Rake credited to a referrer. Holds the claimable lamports on top of its rent.
+/// PDA: ["referral_rewards", referrer].
+#[account]
+pub struct ReferralRewards {
+    pub bump: u8,
+    pub referrer: Pubkey,
+    pub claimable: u64,
+    pub total_earned: u64,
+}
+
+/// Fees credited to an integrator. Holds the claimable lamports on top of its rent.
+/// PDA: ["integrator_rewards", integrator].
+#[account]
+pub struct IntegratorRewards {
+    pub bump: u8,
+    pub integrator: Pubkey,
+    pub claimable: u64,
+    pub total_earned: u64,
+}
+
+impl IntegratorRewards {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // integrator
+        + 8                    // claimable
+        + 8;                   // total_earned
+
+    /// Books a fee already moved into this account.
+    pub fn accrue(&mut self, amount: u64) -> Result<()> {
+        self.claimable = self
+            .claimable
+            .checked_add(amount)
+            .ok_or(RpsError::MathOverflow)?;
+        self.total_earned = self
+            .total_earned
+            .checked_add(amount)
+            .ok_or(RpsError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+impl ReferralRewards {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // referrer
+        + 8                    // claimable
+        + 8;                   // total_earned
+}
+
+/// Lamports a player keeps with the program to stake games from without wallet
+/// transfers. Holds `amount` on top of its rent. PDA: ["balance", owner].
+#[account]
+pub struct PlayerBalance {
+    pub bump: u8,
+    pub owner: Pubkey,
+    pub amount: u64, // available to stake or withdraw
+}
+
+impl PlayerBalance {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // owner
+        + 8;                   // amount
+}
+
+/// Ephemeral key allowed to play for `owner` in all of their games until
+/// `expiry_slot`. PDA: ["session", owner].
+#[account]
+pub struct WalletSession {
+    pub bump: u8,
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub expiry_slot: u64,
+    pub permissions: u8, // `SESSION_PERM_*` within `WALLET_SESSION_PERMS`
+    pub max_bet: u64,    // largest game bet the key may act in, 0 = any
+}
+
+impl WalletSession {
+    pub const SPACE: usize = 8 // discriminator
+        + 1                    // bump
+        + 32                   // owner
+        + 32                   // session_key
+        + 8                    // expiry_slot
+        + 1                    // permissions
+        + 8;                   // max_bet
+}
+
+/// Result of `audit_game` (all amounts in lamports).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct EscrowAudit {
+    pub game_id: [u8; 32],
+    pub status: GameStatus,
+    pub total_pot: u64,
+    pub deposit_p1: u64,
+    pub deposit_p2: u64,
+    pub vault_lamports: u64,
+    pub rent_reserve: u64,
+    pub surplus: u64,   // unsolicited lamports above pot + reserve
+    pub shortfall: u64, // missing from pot + reserve
+    pub deposits_match_pot: bool,
+    pub deposits_match_status: bool,
+    pub healthy: bool,
+}
+
+#[account]
+pub struct Game {
+    pub bump: u8,
+    pub game_id: [u8; 32],
+
+    pub player1: Pubkey,
+    pub player2: Pubkey,
+    pub house_vault: Pubkey,
+
+    pub session_p1: Pubkey, // delegated signer that can act as player1
+    pub session_p2: Pubkey, // delegated signer that can act as player2
+
+    pub bet_amount: u64,
+    pub entry_fee: u64,
+    pub total_pot: u64,
+    pub house_fee_bps: u16,
+
+    pub rounds_played: u8,
+    pub player1_wins: u8,
+    pub player2_wins: u8,
+    pub status: GameStatus,
+
+    pub created_slot: u64, // for timeout logic
+
+    // per-round commit / reveal data
+    pub commitments_p1: [[u8; 32]; MAX_ROUNDS],
+    pub commitments_p2: [[u8; 32]; MAX_ROUNDS],
+    pub committed_p1: [bool; MAX_ROUNDS],
+    pub committed_p2: [bool; MAX_ROUNDS],
+    pub moves_p1: [u8; MAX_ROUNDS],
+    pub moves_p2: [u8; MAX_ROUNDS],
+    pub revealed_p1: [bool; MAX_ROUNDS],
+    pub revealed_p2: [bool; MAX_ROUNDS],
+    pub commit_deadline_slots: [u64; MAX_ROUNDS],
+    // koray-27.11.2025: to prevent double-resolution / reveals after timeout
+    pub round_resolved: [bool; MAX_ROUNDS],
+
+    // bet lamports each seat actually put into game_vault
+    pub deposit_p1: u64,
+    pub deposit_p2: u64,
+    pub emergency_exit_delay_slots: u64, // snapshot for the escape hatch
+
+    // absolute rake caps snapshotted from the rake schedule (max 0 = uncapped)
+    pub min_house_fee: u64,
+    pub max_house_fee: u64,
+
+    // referrers recorded at create/join, and the rake share they get
+    pub referrer_p1: Pubkey,
+    pub referrer_p2: Pubkey,
+    pub referral_share_bps: u16,
+
+    // third-party frontend paid on top of the house rake (default if none)
+    pub integrator: Pubkey,
+    pub integrator_fee_bps: u16,
+
+    // entry fee lamports each seat actually paid into the fee shard
+    pub fee_deposit_p1: u64,
+    pub fee_deposit_p2: u64,
+
+    // seats staked from a `PlayerBalance`; their settlement goes back there
+    pub staked_from_balance_p1: bool,
+    pub staked_from_balance_p2: bool,
+
+    pub fee_refund_policy: FeeRefundPolicy, // snapshot taken at creation
+
+    // session key limits: last usable slot and `SESSION_PERM_*` mask
+    pub session_expiry_p1: u64,
+    pub session_expiry_p2: u64,
+    pub session_perms_p1: u8,
+    pub session_perms_p2: u8,
+
+    // state channel: last co-signed `seq` applied, and end of the dispute window
+    pub channel_seq: u64,
+    pub channel_challenge_deadline: u64,
+
+    // whole-match commitments (Merkle roots); zero if the seat commits per round
+    pub match_root_p1: [u8; 32],
+    pub match_root_p2: [u8; 32],
+
+    pub last_action_slot: u64, // last join / round activity, for emergency_exit
+
+    // end of each round's reveal window, set once both seats are committed
+    pub reveal_deadline_slots: [u64; MAX_ROUNDS],
+}
+
+impl Game {
+    pub const SPACE: usize = 8  // discriminator
+        + 1                     // bump
+        + 32                    // game_id
+        + 32 * 3                // player1, player2, house_vault
+        + 32 * 2                // session_p1, session_p2
+        + 8 * 3                 // bet_amount, entry_fee, total_pot
+        + 2                     // house_fee_bps
+        + 4                     // rounds_played, p1_wins, p2_wins, status (u8)
+        + 8                     // created_at
+        + (32 * MAX_ROUNDS) * 2 // commitments_p1, commitments_p2
+        + MAX_ROUNDS * 2        // committed_p1, committed_p2
+        + MAX_ROUNDS * 2        // moves_p1, moves_p2
+        + MAX_ROUNDS * 2        // revealed_p1, revealed_p2
+        + (8 * MAX_ROUNDS)      // commit_deadline_slots
+        + MAX_ROUNDS            // round_resolved
+        + 8 * 2                 // deposit_p1, deposit_p2
+        + 8                     // emergency_exit_delay_slots
+        + 8 * 2                 // min_house_fee, max_house_fee
+        + 32 * 2                // referrer_p1, referrer_p2
+        + 2                     // referral_share_bps
+        + 32                    // integrator
+        + 2                     // integrator_fee_bps
+        + 8 * 2                 // fee_deposit_p1, fee_deposit_p2
+        + 2                     // staked_from_balance_p1, staked_from_balance_p2
+        + FeeRefundPolicy::SPACE // fee_refund_policy
+        + 8 * 2                 // session_expiry_p1, session_expiry_p2
+        + 2                     // session_perms_p1, session_perms_p2
+        + 8 * 2                 // channel_seq, channel_challenge_deadline
+        + 32 * 2                // match_root_p1, match_root_p2
+        + 8                     // last_action_slot
+        + (8 * MAX_ROUNDS);     // reveal_deadline_slots
+
+    /// Opens round `idx`'s reveal window now that both seats are committed.
+    pub fn open_reveal_window(&mut self, idx: usize, current_slot: u64) -> Result<u64> {
+        let deadline = current_slot
+            .checked_add(REVEAL_PHASE_SLOTS)
+            .ok_or(RpsError::MathOverflow)?;
+        self.reveal_deadline_slots[idx] = deadline;
+        Ok(deadline)
+    }
+
+    /// Last slot round `idx` can be revealed in. Rounds committed before games had
+    /// reveal deadlines get a window right after their commit window.
+    pub fn reveal_deadline(&self, idx: usize) -> Result<u64> {
+        match self.reveal_deadline_slots[idx] {
+            0 => Ok(self.commit_deadline_slots[idx]
+                .checked_add(REVEAL_PHASE_SLOTS)
+                .ok_or(RpsError::MathOverflow)?),
+            deadline => Ok(deadline),
+        }
+    }
+
+    /// Sets up the fields a `migrate_game` left zeroed.
+    ///
+    /// - Legacy games held `bet_amount` per seated player in `total_pot`, so per-seat
+    ///   deposits are rebuilt from it.
+    /// - Their entry fees went to `house_vault_sol`, not a fee shard, so they stay
+    ///   non-refundable (`fee_deposit_p*` = 0) as they were before.
+    /// - Existing session keys keep working with every permission and no expiry.
+    pub fn upgrade_legacy(&mut self) {
+        self.deposit_p1 = self.bet_amount.min(self.total_pot);
+        self.deposit_p2 = self.total_pot - self.deposit_p1;
+        self.emergency_exit_delay_slots = DEFAULT_EMERGENCY_EXIT_DELAY_SLOTS;
+        self.last_action_slot = self.created_slot;
+        if self.session_p1 != Pubkey::default() {
+            self.session_expiry_p1 = u64::MAX;
+            self.session_perms_p1 = SESSION_PERMS;
+        }
+        if self.session_p2 != Pubkey::default() {
+            self.session_expiry_p2 = u64::MAX;
+            self.session_perms_p2 = SESSION_PERMS;
+        }
+    }
+
+    /// Applies an already co-signed channel `state` at `current_slot`.
+    ///
+    /// - Needs a `seq` above the last accepted one.
+    /// - Once a dispute window has closed no channel state is accepted any more, and a
+    ///   state may never roll back on-chain rounds or score.
+    /// - Rounds the state covers are marked resolved so on-chain play resumes after them.
+    /// - A non-final state restarts the dispute window, so the other player always has
+    ///   `CHANNEL_CHALLENGE_SLOTS` to answer the latest one.
+    pub fn apply_channel_state(&mut self, state: &ChannelState, current_slot: u64) -> Result<()> {
+        require!(self.status == GameStatus::Active, RpsError::GameNotActive);
+        state.validate()?;
+
+        require!(state.seq > self.channel_seq, RpsError::StaleChannelState);
+        require!(
+            self.channel_challenge_deadline == 0 || current_slot <= self.channel_challenge_deadline,
+            RpsError::ChannelChallengeClosed
+        );
+        require!(
+            state.rounds_played >= self.rounds_played
+                && state.player1_wins >= self.player1_wins
+                && state.player2_wins >= self.player2_wins,
+            RpsError::InvalidChannelState
+        );
+        for idx in state.rounds_played as usize..MAX_ROUNDS {
+            require!(!self.round_resolved[idx], RpsError::InvalidChannelState);
+        }
+
+        self.channel_seq = state.seq;
+        self.last_action_slot = current_slot;
+        self.rounds_played = state.rounds_played;
+        self.player1_wins = state.player1_wins;
+        self.player2_wins = state.player2_wins;
+        for idx in 0..state.rounds_played as usize {
+            self.round_resolved[idx] = true;
+        }
+
+        if state.is_final() {
+            self.status = GameStatus::Finished;
+        } else {
+            self.channel_challenge_deadline = current_slot
+                .checked_add(CHANNEL_CHALLENGE_SLOTS)
+                .ok_or(RpsError::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// First slot `emergency_exit` may run: the game's delay after whichever came
+    /// first, the house pausing anything or the game's last action.
+    pub fn emergency_exit_unlock_slot(&self, house_vault: &HouseVault) -> Result<u64> {
+        let delay = self.emergency_exit_delay_slots;
+        let idle_unlock = self
+            .last_action_slot
+            .checked_add(delay)
+            .ok_or(RpsError::MathOverflow)?;
+        if house_vault.paused_ops == 0 {
+            return Ok(idle_unlock);
+        }
+        let pause_unlock = house_vault
+            .paused_since_slot
+            .checked_add(delay)
+            .ok_or(RpsError::MathOverflow)?;
+        Ok(idle_unlock.min(pause_unlock))
+    }
+
+    /// Whether `signer` acts for player1 (`true`) or player2 (`false`) in something
+    /// that needs the `permission` session bit.
+    ///
+    /// Players always qualify; their per-game session keys, or the key of a player's
+    /// `wallet_session`, only up to the expiry slot and with that permission granted.
+    pub fn acts_as_player1(
+        &self,
+        signer: &Pubkey,
+        permission: u8,
+        slot: u64,
+        wallet_session: Option<&WalletSession>,
+    ) -> Result<bool> {
+        if *signer == self.player1 {
+            return Ok(true);
+        }
+        if *signer == self.player2 {
+            return Ok(false);
+        }
+
+        let sessions = [
+            (self.session_p1, self.session_expiry_p1, self.session_perms_p1, true),
+            (self.session_p2, self.session_expiry_p2, self.session_perms_p2, false),
+        ];
+        for (session, expiry_slot, perms, is_p1) in sessions {
+            if session != Pubkey::default() && *signer == session {
+                require!(slot <= expiry_slot, RpsError::SessionExpired);
+                require!(perms & permission != 0, RpsError::SessionNotPermitted);
+                return Ok(is_p1);
+            }
+        }
+
+        if let Some(session) = wallet_session {
+            let is_p1 = session.owner == self.player1;
+            if *signer == session.session_key && (is_p1 || session.owner == self.player2) {
+                require!(slot <= session.expiry_slot, RpsError::SessionExpired);
+                require!(session.permissions & permission != 0, RpsError::SessionNotPermitted);
+                require!(
+                    session.max_bet == 0 || self.bet_amount <= session.max_bet,
+                    RpsError::SessionNotPermitted
+                );
+                return Ok(is_p1);
+            }
+        }
+        err!(RpsError::NotAPlayer)
+    }
+
+    /// Rake taken from a decided `pot`, using the rate and caps snapshotted at creation.
+    pub fn house_fee(&self, pot: u64) -> Result<u64> {
+        let mut fee = pot
+            .checked_mul(self.house_fee_bps as u64)
+            .ok_or(RpsError::MathOverflow)?
+            .checked_div(BPS_DENOMINATOR)
+            .ok_or(RpsError::MathOverflow)?;
+
+        fee = fee.max(self.min_house_fee);
+        if self.max_house_fee != 0 {
+            fee = fee.min(self.max_house_fee);
+        }
+        Ok(fee.min(pot))
+    }
+
+    /// Bet and entry fee an `admin_force_refund` returns to each seat, player1 first.
+    ///
+    /// Only games still holding their deposits qualify: settled games are closed and
+    /// cancelled ones already refunded.
+    pub fn force_refund_amounts(&self, refund_entry_fees: bool) -> Result<[(u64, u64); 2]> {
+        require!(
+            self.status == GameStatus::WaitingForPlayer2
+                || self.status == GameStatus::Active
+                || self.status == GameStatus::Finished,
+            RpsError::GameNotCancellable
+        );
+        let (fee_p1, fee_p2) = if refund_entry_fees {
+            (self.fee_deposit_p1, self.fee_deposit_p2)
+        } else {
+            (0, 0)
+        };
+        Ok([(self.deposit_p1, fee_p1), (self.deposit_p2, fee_p2)])
+    }
+
+    /// Compares the game's escrow records with its `game_vault` account.
+    ///
+    /// While the game is open the vault must hold `total_pot` plus its rent reserve,
+    /// deposits must add up to the pot and match the seats taken. Settled and
+    /// cancelled games hold nothing in escrow.
+    pub fn audit_escrow(&self, game_vault: &AccountInfo, rent: &Rent) -> EscrowAudit {
+        let escrow_open = matches!(
+            self.status,
+            GameStatus::WaitingForPlayer2 | GameStatus::Active | GameStatus::Finished
+        );
+        let escrowed = if escrow_open { self.total_pot } else { 0 };
+
+        // legacy system-owned vaults were funded without a rent reserve
+        let rent_reserve = if escrow_open && game_vault.owner == &crate::ID {
+            rent.minimum_balance(game_vault.data_len())
+        } else {
+            0
+        };
+        let expected = escrowed.saturating_add(rent_reserve);
+        let vault_lamports = game_vault.lamports();
+
+        let deposits_match_pot =
+            self.deposit_p1.checked_add(self.deposit_p2) == Some(self.total_pot);
+        let has_player2 = self.player2 != Pubkey::default();
+        let deposits_match_status = match self.status {
+            GameStatus::WaitingForPlayer2 => {
+                !has_player2
+                    && self.deposit_p1 > 0
+                    && self.deposit_p2 == 0
+                    && self.fee_deposit_p2 == 0
+            }
+            GameStatus::Active | GameStatus::Finished => {
+                has_player2 && self.deposit_p1 > 0 && self.deposit_p2 > 0
+            }
+            GameStatus::Settled | GameStatus::Cancelled => true,
+        };
+        let shortfall = expected.saturating_sub(vault_lamports);
+
+        EscrowAudit {
+            game_id: self.game_id,
+            status: self.status,
+            total_pot: self.total_pot,
+            deposit_p1: self.deposit_p1,
+            deposit_p2: self.deposit_p2,
+            vault_lamports,
+            rent_reserve,
+            surplus: vault_lamports.saturating_sub(expected),
+            shortfall,
+            deposits_match_pot,
+            deposits_match_status,
+            healthy: deposits_match_pot && deposits_match_status && shortfall == 0,
+        }
+    }
+
+    /// Integrator fee taken from a decided `pot`, on top of the house fee.
+    ///
+    /// Capped at what `house_fee` leaves of the pot, so the two fees together can
+    /// never exceed it.
+    pub fn integrator_fee(&self, pot: u64, house_fee: u64) -> Result<u64> {
+        let fee = pot
+            .checked_mul(self.integrator_fee_bps as u64)
+            .ok_or(RpsError::MathOverflow)?
+            .checked_div(BPS_DENOMINATOR)
+            .ok_or(RpsError::MathOverflow)?;
+        Ok(fee.min(pot.saturating_sub(house_fee)))
+    }
+
+    /// Referrer cut of `house_fee` for one seat: `referral_share_bps` of the part
+    /// of the fee attributable to that seat's deposit.
+    pub fn referral_reward(&self, house_fee: u64, seat_deposit: u64) -> Result<u64> {
+        if self.total_pot == 0 {
+            return Ok(0);
+        }
+
+        let reward = (house_fee as u128)
+            .checked_mul(seat_deposit as u128)
+            .and_then(|v| v.checked_div(self.total_pot as u128))
+            .and_then(|v| v.checked_mul(self.referral_share_bps as u128))
+            .and_then(|v| v.checked_div(BPS_DENOMINATOR as u128))
+            .ok_or(RpsError::MathOverflow)?;
+        u64::try_from(reward).map_err(|_| error!(RpsError::MathOverflow))
+    }
+}
+
+
+// ---------- Events ----------
+
+#[event]
+pub struct RoundPhaseEvent {
+    pub game_id: [u8; 32],
+    pub round: u8,
+    pub current_slot: u64,
+    pub commit_deadline_slot: u64,
+    pub reveal_deadline_slot: u64,
+    pub both_committed: bool,
+}
+
+#[event]
+pub struct RoundResultEvent {
+    pub game_id: [u8; 32],
+    pub round: u8,
+    pub player1_wins: u8,
+    pub player2_wins: u8,
+    pub rounds_played: u8,
+    pub status: GameStatus,
+}
+
+#[event]
+pub struct RoundStartEvent {
+    pub game_id: [u8; 32],
+    pub round: u8,
+    pub start_slot: u64,
+    pub commit_deadline_slot: u64,
+}
+
+#[event]
+pub struct MatchRootCommittedEvent {
+    pub game_id: [u8; 32],
+    pub player: Pubkey,
+    pub root: [u8; 32],
+}
+
+#[event]
+pub struct GameForfeitEvent {
+    pub game_id: [u8; 32],
+    pub loser: Pubkey,
+    pub winner: Pubkey,
+}
+
+#[event]
+pub struct GameCancelledEvent {
+    pub game_id: [u8; 32],
+    pub player1: Pubkey,
+    pub player2: Pubkey,
+    pub player1_refund: u64,
+    pub player2_refund: u64,
+    pub reason: CancelReason,
+    pub player1_fee_refund: u64, // entry fees returned from the fee shard
+    pub player2_fee_refund: u64,
+}
+
+#[event]
+pub struct EntryFeeRefundShortfallEvent {
+    pub game_id: [u8; 32],
+    pub player: Pubkey,
+    pub owed: u64, // per the game's fee refund policy
+    pub paid: u64, // what the fee shard could cover
+}
+
+#[event]
+pub struct HouseFeeScheduledEvent {
+    pub current_fee_bps: u16,
+    pub pending_fee_bps: u16,
+    pub activation_slot: u64,
+}
+
+#[event]
+pub struct HouseFeeCancelledEvent {
+    pub cancelled_fee_bps: u16,
+    pub current_fee_bps: u16,
+}
+
+#[event]
+pub struct RakeScheduleScheduledEvent {
+    pub schedule: RakeSchedule,
+    pub activation_slot: u64,
+}
+
+#[event]
+pub struct RakeScheduleCancelledEvent {
+    pub current_schedule: RakeSchedule,
+}
+
+#[event]
+pub struct RoleGrantedEvent {
+    pub member: Pubkey,
+    pub role: Role,
+    pub roles: u8,
+}
+
+#[event]
+pub struct RoleRevokedEvent {
+    pub member: Pubkey,
+    pub role: Role,
+    pub roles: u8,
+}
+
+#[event]
+pub struct PausedEvent {
+    pub authority: Pubkey,
+    pub ops: u8,
+    pub paused_ops: u8,
+}
+
+#[event]
+pub struct UnpausedEvent {
+    pub authority: Pubkey,
+    pub ops: u8,
+    pub paused_ops: u8,
+}
+
+#[event]
+pub struct AdminProposedEvent {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferredEvent {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct ProposalCreatedEvent {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+    pub expiry_slot: u64,
+}
+
+#[event]
+pub struct ProposalApprovedEvent {
+    pub proposal_id: u64,
+    pub admin: Pubkey,
+    pub approvals: u8,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct ProposalCancelledEvent {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+}
+
+#[event]
+pub struct ProposalExecutedEvent {
+    pub proposal_id: u64,
+    pub executor: Pubkey,
+}
+
+#[event]
+pub struct FeeShardsSweptEvent {
+    pub shards: u8,
+    pub total_swept: u64,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct EpochReportRecordedEvent {
+    pub epoch: u64,
+    pub stats: HouseStats,
+    pub fee_shard_balance: u64,
+    pub house_vault_balance: u64,
+}
+
+#[event]
+pub struct PayeesUpdatedEvent {
+    pub split: PayeeSplit,
+}
+
+#[event]
+pub struct HouseFundsDistributedEvent {
+    pub split: PayeeSplit,
+    pub amounts: [u64; MAX_PAYEES], // paid per payee, same order as split
+    pub total_distributed: u64,
+    pub caller: Pubkey,
+}
+
+#[event]
+pub struct HouseLiabilitiesChangedEvent {
+    pub liabilities: u64, // new total
+    pub treasurer: Pubkey,
+}
+
+#[event]
+pub struct EmergencyExitEvent {
+    pub game_id: [u8; 32],
+    pub player1: Pubkey,
+    pub player2: Pubkey,
+    pub player1_refund: u64,
+    pub player2_refund: u64,
+}
+
+#[event]
+pub struct GameSettledEvent {
+    pub game_id: [u8; 32],
+    pub winner: Pubkey, // default on a draw
+    pub payout_p1: u64,
+    pub payout_p2: u64,
+    pub house_fee: u64, // includes referral rewards
+    pub referral_reward_p1: u64,
+    pub referral_reward_p2: u64,
+    pub integrator: Pubkey, // default if none
+    pub integrator_fee: u64,
+}
+
+#[event]
+pub struct ReferrerRegisteredEvent {
+    pub player: Pubkey,
+    pub referrer: Pubkey,
+}
+
+#[event]
+pub struct IntegratorRegisteredEvent {
+    pub integrator: Pubkey,
+}
+
+#[event]
+pub struct IntegratorRewardsClaimedEvent {
+    pub integrator: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimedEvent {
+    pub referrer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BalanceDepositedEvent {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub balance: u64, // after the deposit
+}
+
+#[event]
+pub struct BalanceWithdrawnEvent {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub balance: u64, // after the withdrawal
+}
+
+#[event]
+pub struct SessionAuthorizedEvent {
+    pub game_id: [u8; 32],
+    pub player: Pubkey,
+    pub session: Pubkey,
+    pub expiry_slot: u64,
+    pub permissions: u8,
+}
+
+#[event]
+pub struct SessionRevokedEvent {
+    pub game_id: [u8; 32],
+    pub player: Pubkey,
+    pub session: Pubkey,
+}
+
+#[event]
+pub struct WalletSessionOpenedEvent {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub expiry_slot: u64,
+    pub permissions: u8,
+    pub max_bet: u64,
+    pub top_up: u64,
+}
+
+#[event]
+pub struct WalletSessionClosedEvent {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub refunded: u64, // lamports swept back from the session key
+}
+
+#[event]
+pub struct ChannelStateSubmittedEvent {
+    pub game_id: [u8; 32],
+    pub seq: u64,
+    pub player1_wins: u8,
+    pub player2_wins: u8,
+    pub rounds_played: u8,
+    pub status: GameStatus,
+    pub challenge_deadline_slot: u64, // 0 if no dispute was opened
+}
+
+#[event]
+pub struct AdminForceRefundEvent {
+    pub game_id: [u8; 32],
+    pub player1: Pubkey,
+    pub player2: Pubkey,
+    pub player1_refund: u64,
+    pub player2_refund: u64,
+    pub player1_fee_refund: u64,
+    pub player2_fee_refund: u64,
+    pub admin: Pubkey,
+}
+
+// ---------- Instruction Contexts ----------
+
+#[derive(Accounts)]
+pub struct InitHouseVault<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ RpsError::InvalidProgramData
+    )]
+    pub program: Program<'info, crate::program::RpsGame>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ RpsError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = HouseVault::SPACE,
+        seeds = [b"house_vault"],
+        bump
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// CHECK: PDA used as the on-chain SOL vault for house fees. Created and constrained by
+    /// seeds + bump, only used as a lamport vault, never deserialized. Program-owned so that
+    /// withdrawals debit it directly.
+    #[account(
+        init,
+        payer = admin,
+        space = 0,
+        seeds = [b"house_vault_sol"],
+        bump,
+        owner = crate::ID
+    )]
+    pub house_vault_sol: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: [u8; 32])]
+pub struct CreateGame<'info> {
+    #[account(mut)]
+    pub player1: Signer<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// House fee shard picked by `game_id`; receives player1's entry fee.
+    #[account(
+        mut,
+        seeds = [b"fee_shard", [fee_shard_index(&game_id)].as_ref()],
+        bump = fee_shard.bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    /// CHECK: Player1's referral PDA, always passed so a registered referrer can't be
+    /// left out. Address checked via seeds; read only if it holds a `Referral`.
+    #[account(
+        seeds = [b"referral", player1.key().as_ref()],
+        bump
+    )]
+    pub player1_referral: UncheckedAccount<'info>,
+
+    /// Rewards account of the frontend hosting this game; its fee accrues there.
+    #[account(
+        seeds = [b"integrator_rewards", integrator_rewards.integrator.as_ref()],
+        bump = integrator_rewards.bump
+    )]
+    pub integrator_rewards: Option<Account<'info, IntegratorRewards>>,
+
+    /// Player1's balance account; when passed, the stake is taken from it.
+    #[account(
+        mut,
+        seeds = [b"balance", player1.key().as_ref()],
+        bump = player1_balance.bump
+    )]
+    pub player1_balance: Option<Account<'info, PlayerBalance>>,
+
+    #[account(
+        init,
+        payer = player1,
+        space = Game::SPACE,
+        seeds = [b"game", game_id.as_ref()],
+        bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: Per-game pot vault PDA. Address is derived via seeds + bump and only holds lamports.
+    /// Program-owned so that payouts and refunds debit it directly.
+    #[account(
+        init,
+        payer = player1,
+        space = 0,
+        seeds = [b"game_vault", game_id.as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    pub game_vault: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinGame<'info> {
+    #[account(mut)]
+    pub player2: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump,
+        constraint = game.player1 != Pubkey::default() @ RpsError::InvalidGameState
+    )]
+    pub game: Account<'info, Game>,
+
+    /// CHECK: Same per-game pot PDA created in `CreateGame`. Address checked via seeds + bump.
+    #[account(
+        mut,
+        seeds = [b"game_vault", &game.game_id],
+        bump,
+        constraint = is_vault_owner(game_vault.owner) @ RpsError::InvalidVaultOwner
+    )]
+    pub game_vault: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+        constraint = house_vault.key() == game.house_vault @ RpsError::InvalidHouseWallet
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// Same house fee shard as in `CreateGame`; receives player2's entry fee.
+    #[account(
+        mut,
+        seeds = [b"fee_shard", [fee_shard_index(&game.game_id)].as_ref()],
+        bump = fee_shard.bump
+    )]
+    pub fee_shard: Account<'info, FeeShard>,
+
+    /// CHECK: Player2's referral PDA, always passed so a registered referrer can't be
+    /// left out. Address checked via seeds; read only if it holds a `Referral`.
+    #[account(
+        seeds = [b"referral", player2.key().as_ref()],
+        bump
+    )]
+    pub player2_referral: UncheckedAccount<'info>,
+
+    /// Player2's balance account; when passed, the stake is taken from it.
+    #[account(
+        mut,
+        seeds = [b"balance", player2.key().as_ref()],
+        bump = player2_balance.bump
+    )]
+    pub player2_balance: Option<Account<'info, PlayerBalance>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitMove<'info> {
+    #[account(
+        mut,
+        seeds = [b"game", &game.game_id],
+        bump = game.bump
+    )]
+    pub game: Account<'info, Game>,
+
+    /// A player or a session key authorized for this game or by the player's wallet
+    /// session (checked in the handler)
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    /// Wallet session of the player `player` is acting for, if it is that session's key.
+    #[account(
+        seeds = [b"session", wallet_session.owner.as_ref()],
+        bump = wallet_session.bump
+    )]
+    pub wallet_session: Option<Account<'info, WalletSession>>,
 }
 
 #[derive(Accounts)]
-pub struct StartRound<'info> {
+pub struct RevealMove<'info> {
     #[account(
         mut,
         seeds = [b"game", &game.game_id],
@@ -1004,23 +6019,24 @@ pub struct StartRound<'info> {
     )]
     pub game: Account<'info, Game>,
 
-    /// Must be a player or their authorized session key
+    /// A player or a session key authorized for this game or by the player's wallet
+    /// session (checked in the handler)
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    /// Wallet session of the player `player` is acting for, if it is that session's key.
     #[account(
-        constraint =
-            caller.key() == game.player1 ||
-            caller.key() == game.player2 ||
-            caller.key() == game.session_p1 ||
-            caller.key() == game.session_p2
-            @ RpsError::NotAPlayer
+        seeds = [b"session", wallet_session.owner.as_ref()],
+        bump = wallet_session.bump
     )]
-    pub caller: Signer<'info>,
+    pub wallet_session: Option<Account<'info, WalletSession>>,
 }
 
 
 #[derive(Accounts)]
-pub struct ResolveCommitTimeout<'info> {
-    /// Anyone can call this (mediator, player, random user).
-    pub caller: Signer<'info>,
+pub struct RelayedMove<'info> {
+    /// Submits and pays for the transaction; the player signs the message instead.
+    pub relayer: Signer<'info>,
 
     #[account(
         mut,
@@ -1028,20 +6044,16 @@ pub struct ResolveCommitTimeout<'info> {
         bump = game.bump
     )]
     pub game: Account<'info, Game>,
+
+    /// CHECK: Instructions sysvar, used to inspect the preceding Ed25519 instruction.
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ForfeitGame<'info> {
-    /// Must be one of the players or their session key
-    #[account(
-        constraint =
-            caller.key() == game.player1 ||
-            caller.key() == game.player2 ||
-            caller.key() == game.session_p1 ||
-            caller.key() == game.session_p2
-            @ RpsError::NotAPlayer
-    )]
-    pub caller: Signer<'info>,
+pub struct SubmitChannelState<'info> {
+    /// Either player or a relayer; the players' signatures come from the Ed25519 check.
+    pub submitter: Signer<'info>,
 
     #[account(
         mut,
@@ -1049,547 +6061,1636 @@ pub struct ForfeitGame<'info> {
         bump = game.bump
     )]
     pub game: Account<'info, Game>,
+
+    /// CHECK: Instructions sysvar, used to inspect the preceding Ed25519 instruction.
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CancelGame<'info> {
-    /// Anyone can call cancel_game
-    pub caller: Signer<'info>,
-
+pub struct SettleGame<'info> {
     #[account(
         mut,
+        close = player1, // <-- let Anchor close & refund rent to player1
         seeds = [b"game", &game.game_id],
         bump = game.bump
     )]
     pub game: Account<'info, Game>,
 
-    /// CHECK: Player 1 account to receive refund
+    /// CHECK: safe because of the `address = game.player1` constraint
+    #[account(mut, address = game.player1 @ RpsError::InvalidPlayerAccount)]
+    pub player1: AccountInfo<'info>,
+
+    /// CHECK: safe because of the `address = game.player2` constraint
+    #[account(mut, address = game.player2 @ RpsError::InvalidPlayerAccount)]
+    pub player2: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"house_vault"],
+        bump = house_vault.bump,
+        constraint = house_vault.key() == game.house_vault @ RpsError::InvalidHouseWallet
+    )]
+    pub house_vault: Account<'info, HouseVault>,
+
+    /// House fee shard for this game; receives the rake.
     #[account(
         mut,
-        constraint = player1.key() == game.player1 @ RpsError::InvalidPlayerAccount
+        seeds = [b"fee_shard", [fee_shard_index(&game.game_id)].as_ref()],
+        bump = fee_shard.bump
     )]
-    pub player1: AccountInfo<'info>,
+    pub fee_shard: Account<'info, FeeShard>,
 
-    /// CHECK: Player 2 account to receive refund
+    /// Rewards account of player1's referrer; required if the game recorded one.
+    /// When both players share a referrer, only this account may be passed.
     #[account(
         mut,
-        constraint = player2.key() == game.player2 @ RpsError::InvalidPlayerAccount
+        seeds = [b"referral_rewards", game.referrer_p1.as_ref()],
+        bump = referrer_rewards_p1.bump
     )]
-    pub player2: AccountInfo<'info>,
+    pub referrer_rewards_p1: Option<Account<'info, ReferralRewards>>,
 
-    /// Game vault PDA that holds the bet
-    /// CHECK: This is a PDA that holds SOL, not an Anchor account
+    /// Rewards account of player2's referrer; required if the game recorded one.
+    #[account(
+        mut,
+        seeds = [b"referral_rewards", game.referrer_p2.as_ref()],
+        bump = referrer_rewards_p2.bump
+    )]
+    pub referrer_rewards_p2: Option<Account<'info, ReferralRewards>>,
+
+    /// Rewards account of the game's integrator; required only when the game has one.
+    #[account(
+        mut,
+        seeds = [b"integrator_rewards", game.integrator.as_ref()],
+        bump = integrator_rewards.bump
+    )]
+    pub integrator_rewards: Option<Account<'info, IntegratorRewards>>,
+
+    /// Player1's balance account; required if player1 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player1.as_ref()],
+        bump = player1_balance.bump
+    )]
+    pub player1_balance: Option<Account<'info, PlayerBalance>>,
+
+    /// Player2's balance account; required if player2 staked from it.
+    #[account(
+        mut,
+        seeds = [b"balance", game.player2.as_ref()],
+        bump = player2_balance.bump
+    )]
+    pub player2_balance: Option<Account<'info, PlayerBalance>>,
+
+    /// CHECK: Game pot SOL vault PDA; address enforced via seeds + bump, only used for lamports.
     #[account(
         mut,
         seeds = [b"game_vault", &game.game_id],
-        bump
+        bump,
+        constraint = is_vault_owner(game_vault.owner) @ RpsError::InvalidVaultOwner
     )]
-    pub game_vault: AccountInfo<'info>,
+    pub game_vault: UncheckedAccount<'info>,
 
     pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-pub struct AuthorizeSessionSigner<'info> {
-    #[account(mut)]
-    pub player: Signer<'info>,
+// ---------- Errors ----------
+
+#[error_code]
+pub enum RpsError {
+    #[msg("Invalid bet amount")]
+    InvalidBetAmount,
+    #[msg("Invalid entry fee")]
+    InvalidEntryFee,
+    #[msg("Game is not joinable")]
+    GameNotJoinable,
+    #[msg("Game already has a second player")]
+    AlreadyHasPlayer2,
+    #[msg("Game is not active")]
+    GameNotActive,
+    #[msg("Game is not finished")]
+    GameNotFinished,
+    #[msg("Invalid round index")]
+    InvalidRound,
+    #[msg("Invalid move")]
+    InvalidMove,
+    #[msg("Player is not part of this game")]
+    NotAPlayer,
+    #[msg("Already committed for this round")]
+    AlreadyCommitted,
+    #[msg("Not committed yet for this round")]
+    NotCommittedYet,
+    #[msg("Already revealed for this round")]
+    AlreadyRevealed,
+    #[msg("Commitment hash does not match")]
+    CommitmentMismatch,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Invalid game state")]
+    InvalidGameState,
+    #[msg("Invalid house wallet")]
+    InvalidHouseWallet,
+    #[msg("Invalid player account")]
+    InvalidPlayerAccount,
+    #[msg("Unauthorized admin")]
+    Unauthorized,
+    #[msg("Game not cancellable in this state")]
+    GameNotCancellable,
+    #[msg("Game has not timed out yet")]
+    NotTimedOut,
+    #[msg("Invalid house fee bps")]
+    InvalidHouseFee,
+    #[msg("Bet is below minimum allowed")]
+    BetTooLow,
+    #[msg("Commit phase for this round has expired")]
+    CommitPhaseExpired,
+    #[msg("Both players must commit before reveal")]
+    BothMustCommitFirst,
+    #[msg("Round already resolved")]
+    RoundAlreadyResolved,
+    #[msg("Commit window not started for this round")]
+    CommitWindowNotStarted,
+    #[msg("Commit phase not yet expired")]
+    CommitPhaseNotExpired,
+    #[msg("Both players committed, timeout resolve not allowed")]
+    BothCommittedNoTimeout,
+    #[msg("Commit window already started for this round")]
+    CommitWindowAlreadyStarted,
+    #[msg("Vault account has an unexpected owner")]
+    InvalidVaultOwner,
+    #[msg("Invalid fee shard")]
+    InvalidFeeShard,
+    #[msg("Invalid admin set")]
+    InvalidAdminSet,
+    #[msg("Invalid withdraw amount")]
+    InvalidWithdrawAmount,
+    #[msg("Proposal was made for a previous admin set")]
+    StaleProposal,
+    #[msg("Proposal has expired")]
+    ProposalExpired,
+    #[msg("Proposal does not have enough approvals")]
+    NotEnoughApprovals,
+    #[msg("Admin already approved this proposal")]
+    AlreadyApproved,
+    #[msg("Proposal action does not match this instruction")]
+    ProposalActionMismatch,
+    #[msg("Account is not the proposer")]
+    InvalidProposer,
+    #[msg("Recipient does not match the proposal")]
+    InvalidRecipient,
+    #[msg("ProgramData account does not belong to this program")]
+    InvalidProgramData,
+    #[msg("Signer does not hold the required role")]
+    MissingRole,
+    #[msg("This operation is paused")]
+    Paused,
+    #[msg("Invalid pause operations mask")]
+    InvalidPauseOps,
+    #[msg("Emergency exit delay out of bounds")]
+    InvalidEmergencyExitDelay,
+    #[msg("Emergency exit is not available yet")]
+    EmergencyExitLocked,
+    #[msg("No house fee change is pending")]
+    NoPendingHouseFee,
+    #[msg("Pending house fee is already active")]
+    HouseFeeAlreadyActive,
+    #[msg("Invalid rake schedule")]
+    InvalidRakeSchedule,
+    #[msg("Referral share exceeds maximum")]
+    InvalidReferralShare,
+    #[msg("Invalid referrer")]
+    InvalidReferrer,
+    #[msg("Referrer rewards account missing")]
+    MissingReferrerRewards,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Invalid integrator fee")]
+    InvalidIntegratorFee,
+    #[msg("Invalid integrator account")]
+    InvalidIntegratorAccount,
+    #[msg("Invalid payees")]
+    InvalidPayees,
+    #[msg("Invalid liability amount")]
+    InvalidLiabilityAmount,
+    #[msg("Insufficient house funds")]
+    InsufficientHouseFunds,
+    #[msg("Withdrawal cap exceeded")]
+    WithdrawalCapExceeded,
+    #[msg("Invalid epoch")]
+    InvalidEpoch,
+    #[msg("Escrow invariant violated")]
+    EscrowInvariantViolated,
+    #[msg("Invalid balance amount")]
+    InvalidBalanceAmount,
+    #[msg("Insufficient balance")]
+    InsufficientBalance,
+    #[msg("Missing player balance account")]
+    MissingPlayerBalance,
+    #[msg("Invalid fee refund policy")]
+    InvalidFeeRefundPolicy,
+    #[msg("Invalid session")]
+    InvalidSession,
+    #[msg("Session expired")]
+    SessionExpired,
+    #[msg("Session not permitted to do this")]
+    SessionNotPermitted,
+    #[msg("Invalid relayed signature")]
+    InvalidRelayedSignature,
+    #[msg("Invalid channel state")]
+    InvalidChannelState,
+    #[msg("Channel state is not newer than the last one")]
+    StaleChannelState,
+    #[msg("Channel dispute window is still open")]
+    ChannelChallengeActive,
+    #[msg("Channel dispute window has closed")]
+    ChannelChallengeClosed,
+    #[msg("Invalid or missing match root")]
+    InvalidMatchRoot,
+    #[msg("Merkle proof does not match the match root")]
+    InvalidMatchProof,
+    #[msg("Account is not a legacy layout that needs migration")]
+    NothingToMigrate,
+    #[msg("Shared referrer's rewards account passed twice")]
+    DuplicateReferrerRewards,
+    #[msg("Player1 cannot join their own game")]
+    CannotJoinOwnGame,
+    #[msg("Reveal phase for this round has expired")]
+    RevealPhaseExpired,
+    #[msg("Reveal phase not yet expired")]
+    RevealPhaseNotExpired,
+    #[msg("Only the caller's own seat can be forfeited")]
+    ForfeitForOtherSeat,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::program_error::ProgramError;
+    use anchor_lang::InstructionData;
+    use solana_sysvar::program_stubs::{set_syscall_stubs, SyscallStubs};
+    use std::cell::Cell;
+    use std::sync::Once;
+
+    /// Account of type `T` as stored in `space` zeroed bytes behind its discriminator.
+    fn zeroed<T: AccountDeserialize + Discriminator>(space: usize) -> T {
+        let mut data = vec![0u8; space];
+        data[..8].copy_from_slice(T::DISCRIMINATOR);
+        T::try_deserialize(&mut &data[..]).unwrap()
+    }
+
+    /// Bytes of `account` cut back to a legacy `len` and zero-extended again, the way
+    /// `grow_account` leaves them.
+    fn regrown<T>(account: &T, len: usize, space: usize) -> T
+    where
+        T: AccountSerialize + AccountDeserialize,
+    {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data.truncate(len);
+        data.resize(space, 0);
+        T::try_deserialize(&mut &data[..]).unwrap()
+    }
+
+    /// Zeroed `Game` with `setup` applied.
+    fn game_with(setup: impl FnOnce(&mut Game)) -> Game {
+        let mut game: Game = zeroed(Game::SPACE);
+        setup(&mut game);
+        game
+    }
+
+    /// An account's key and data as the runtime serializes them: `realloc` (and so
+    /// Anchor's `close`) reads the original data length in front of the key and
+    /// writes the new length in front of the data.
+    #[repr(C)]
+    struct SerializedKey {
+        original_data_len: u32,
+        key: Pubkey,
+    }
+
+    /// Writable account at `key`, leaked so contexts can borrow it for `'static`.
+    fn account_info(
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    ) -> &'static mut AccountInfo<'static> {
+        let len = data.len();
+        let serialized_key = Box::leak(Box::new(SerializedKey {
+            original_data_len: len as u32,
+            key,
+        }));
+        // u64 words keep the length prefix aligned
+        let words = Box::leak(vec![0u64; 1 + len.div_ceil(8)].into_boxed_slice());
+        words[0] = len as u64;
+        let serialized_data = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr().add(1).cast::<u8>(), len)
+        };
+        serialized_data.copy_from_slice(&data);
+
+        Box::leak(Box::new(AccountInfo::new(
+            &serialized_key.key,
+            false,
+            true,
+            Box::leak(Box::new(lamports)),
+            serialized_data,
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )))
+    }
+
+    /// System-owned wallet holding `lamports`.
+    fn wallet(lamports: u64) -> &'static mut AccountInfo<'static> {
+        account_info(Pubkey::new_unique(), system_program::ID, lamports, Vec::new())
+    }
+
+    /// Program-owned account at `key` holding `value`, funded to its rent minimum.
+    fn program_account<T: AccountSerialize>(
+        key: Pubkey,
+        value: &T,
+        space: usize,
+    ) -> &'static mut AccountInfo<'static> {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        data.resize(space, 0);
+        let lamports = Rent::default().minimum_balance(space);
+        account_info(key, crate::ID, lamports, data)
+    }
+
+    fn system_program() -> Program<'static, System> {
+        let info = account_info(
+            system_program::ID,
+            solana_sdk_ids::native_loader::ID,
+            1,
+            Vec::new(),
+        );
+        info.executable = true;
+        Program::try_from(&*info).unwrap()
+    }
+
+    fn signer(key: Pubkey) -> &'static mut AccountInfo<'static> {
+        let info = account_info(key, system_program::ID, 1_000 * MIN_BET_LAMPORTS, Vec::new());
+        info.is_signer = true;
+        info
+    }
+
+    fn pda(seeds: &[&[u8]]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(seeds, &crate::ID)
+    }
+
+    /// `house_vault` PDA with `setup` applied.
+    fn house_vault_with(setup: impl FnOnce(&mut HouseVault)) -> &'static mut AccountInfo<'static> {
+        let (key, bump) = pda(&[b"house_vault"]);
+        let mut vault: HouseVault = zeroed(HouseVault::SPACE);
+        vault.bump = bump;
+        setup(&mut vault);
+        program_account(key, &vault, HouseVault::SPACE)
+    }
+
+    /// Migrated (program-owned) `house_vault_sol` holding `balance` above its rent minimum.
+    fn house_vault_sol(balance: u64) -> &'static mut AccountInfo<'static> {
+        let lamports = Rent::default().minimum_balance(0) + balance;
+        account_info(pda(&[b"house_vault_sol"]).0, crate::ID, lamports, Vec::new())
+    }
+
+    /// Fee shard PDA `index` holding `balance` above its rent minimum.
+    fn fee_shard(index: u8, balance: u64, stats: HouseStats) -> &'static mut AccountInfo<'static> {
+        let (key, bump) = pda(&[b"fee_shard", &[index]]);
+        let shard = FeeShard { bump, index, stats };
+        let info = program_account(key, &shard, FeeShard::SPACE);
+        **info.lamports.borrow_mut() += balance;
+        info
+    }
+
+    fn load<T: AccountDeserialize>(info: &AccountInfo) -> T {
+        T::try_deserialize(&mut &info.data.borrow()[..]).unwrap()
+    }
+
+    /// Stands in for an optional account left out of an instruction.
+    fn omitted() -> &'static mut AccountInfo<'static> {
+        account_info(crate::ID, Pubkey::default(), 0, Vec::new())
+    }
+
+    /// Game PDA for `game` and its `game_vault` holding the pot above its rent minimum.
+    fn game_accounts(
+        mut game: Game,
+    ) -> (&'static mut AccountInfo<'static>, &'static mut AccountInfo<'static>) {
+        let (key, bump) = pda(&[b"game", &game.game_id]);
+        game.bump = bump;
+        let vault_key = pda(&[b"game_vault", &game.game_id]).0;
+        let vault_lamports = Rent::default().minimum_balance(0) + game.total_pot;
+        (
+            program_account(key, &game, Game::SPACE),
+            account_info(vault_key, crate::ID, vault_lamports, Vec::new()),
+        )
+    }
+
+    fn above_rent(info: &AccountInfo) -> u64 {
+        info.lamports() - Rent::default().minimum_balance(info.data_len())
+    }
+
+    thread_local! {
+        static CLOCK: Cell<(u64, u64)> = const { Cell::new((0, 0)) }; // (slot, epoch)
+    }
+
+    /// Sets the clock the handlers on this thread see.
+    fn set_clock(slot: u64, epoch: u64) {
+        install_runtime();
+        CLOCK.with(|clock| clock.set((slot, epoch)));
+    }
+
+    fn install_runtime() {
+        static RUNTIME: Once = Once::new();
+        RUNTIME.call_once(|| {
+            set_syscall_stubs(Box::new(TestRuntime));
+        });
+    }
+
+    /// Clock and rent sysvars for handlers run off-chain. CPIs are no-ops there, so
+    /// tests keep vaults program-owned and pre-allocate `init` accounts.
+    struct TestRuntime;
+
+    impl SyscallStubs for TestRuntime {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let (slot, epoch) = CLOCK.with(Cell::get);
+            let clock = Clock { slot, epoch, ..Clock::default() };
+            unsafe { var_addr.cast::<Clock>().write(clock) };
+            0
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { var_addr.cast::<Rent>().write(Rent::default()) };
+            0
+        }
+    }
+
+    /// Runs `instruction` through the program entrypoint with `accounts` in context order.
+    fn process(
+        instruction: impl InstructionData,
+        accounts: &[&AccountInfo<'static>],
+    ) -> std::result::Result<(), ProgramError> {
+        install_runtime();
+        let accounts: Vec<_> = accounts.iter().map(|info| (*info).clone()).collect();
+        crate::entry(&crate::ID, Box::leak(accounts.into_boxed_slice()), &instruction.data())
+    }
+
+    fn program_error(error: RpsError) -> ProgramError {
+        Error::from(error).into()
+    }
+
+    // `HouseVault` and `Game` as the first deployed version laid them out
+    const LEGACY_HOUSE_VAULT_SPACE: usize = 8 + 1 + 32 + 2;
+    const LEGACY_GAME_SPACE: usize = 8 + 1 + 32 + 32 * 3 + 32 * 2 + 8 * 3 + 2 + 4 + 8
+        + (32 * MAX_ROUNDS) * 2
+        + MAX_ROUNDS * 2
+        + MAX_ROUNDS * 2
+        + MAX_ROUNDS * 2
+        + 8 * MAX_ROUNDS
+        + MAX_ROUNDS;
+
+    #[test]
+    fn migrated_house_vault_gets_one_of_one_admin_set() {
+        let mut legacy: HouseVault = zeroed(HouseVault::SPACE);
+        legacy.admin = Pubkey::new_unique();
+        legacy.house_fee_bps = 250;
+
+        let mut vault = regrown(&legacy, LEGACY_HOUSE_VAULT_SPACE, HouseVault::SPACE);
+        vault.upgrade_legacy();
+
+        assert_eq!(vault.house_fee_bps, 250);
+        assert_eq!(vault.admin_index(&legacy.admin), Some(0));
+        assert_eq!((vault.admin_count, vault.threshold), (1, 1));
+        assert_eq!(vault.emergency_exit_delay_slots, DEFAULT_EMERGENCY_EXIT_DELAY_SLOTS);
+    }
+
+    #[test]
+    fn migrated_game_rebuilds_seat_deposits_from_pot() {
+        let mut legacy = game_with(|game| {
+            game.bet_amount = MIN_BET_LAMPORTS;
+            game.total_pot = MIN_BET_LAMPORTS * 2;
+            game.session_p2 = Pubkey::new_unique();
+            game.deposit_p1 = 7; // past the legacy layout, must not survive
+        });
+
+        let mut game = regrown(&legacy, LEGACY_GAME_SPACE, Game::SPACE);
+        game.upgrade_legacy();
+
+        assert_eq!((game.deposit_p1, game.deposit_p2), (MIN_BET_LAMPORTS, MIN_BET_LAMPORTS));
+        assert_eq!((game.fee_deposit_p1, game.fee_deposit_p2), (0, 0));
+        assert_eq!(game.session_perms_p1, 0);
+        assert_eq!(
+            (game.session_expiry_p2, game.session_perms_p2),
+            (u64::MAX, SESSION_PERMS)
+        );
+
+        legacy.total_pot = MIN_BET_LAMPORTS; // nobody joined yet
+        let mut game = regrown(&legacy, LEGACY_GAME_SPACE, Game::SPACE);
+        game.upgrade_legacy();
+        assert_eq!((game.deposit_p1, game.deposit_p2), (MIN_BET_LAMPORTS, 0));
+    }
+
+    #[test]
+    fn emergency_exit_unlocks_after_idle_or_pause_delay() {
+        let mut game = game_with(|game| {
+            game.created_slot = 100;
+            game.last_action_slot = 5_000;
+            game.emergency_exit_delay_slots = 1_000;
+        });
+        let mut house_vault: HouseVault = zeroed(HouseVault::SPACE);
+
+        // a long-running game counts from its last action, not its creation
+        assert_eq!(game.emergency_exit_unlock_slot(&house_vault).unwrap(), 6_000);
+
+        // a fresh pause doesn't unlock anything early
+        house_vault.paused_ops = PAUSE_START_ROUND;
+        house_vault.paused_since_slot = 5_900;
+        assert_eq!(game.emergency_exit_unlock_slot(&house_vault).unwrap(), 6_000);
+
+        // a pause older than the idle window unlocks at its own delay
+        game.last_action_slot = 8_000;
+        assert_eq!(game.emergency_exit_unlock_slot(&house_vault).unwrap(), 6_900);
+    }
+
+    #[test]
+    fn fee_refund_policy_applies_rate_per_cancel_reason() {
+        let policy = FeeRefundPolicy {
+            no_opponent_bps: 10_000,
+            aborted_before_play_bps: 5_000,
+            aborted_mid_game_bps: 0,
+        };
+        policy.validate().unwrap();
+
+        let fee = 1_000_001;
+        assert_eq!(policy.refund_for(fee, CancelReason::NoOpponent).unwrap(), fee);
+        assert_eq!(policy.refund_for(fee, CancelReason::AbortedBeforePlay).unwrap(), 500_000);
+        assert_eq!(policy.refund_for(fee, CancelReason::AbortedMidGame).unwrap(), 0);
+
+        let too_generous = FeeRefundPolicy { no_opponent_bps: 10_001, ..policy };
+        assert!(too_generous.validate().is_err());
+    }
+
+    #[test]
+    fn rake_floor_cannot_exceed_max_rate_on_smallest_pot() {
+        let mut schedule = RakeSchedule {
+            min_fee: MAX_MIN_HOUSE_FEE,
+            ..RakeSchedule::default()
+        };
+        schedule.validate().unwrap();
+
+        schedule.min_fee += 1;
+        assert!(schedule.validate().is_err());
+    }
+
+    #[test]
+    fn house_and_integrator_fees_never_exceed_pot() {
+        let pot = MIN_BET_LAMPORTS * 2;
+        let mut game = game_with(|game| {
+            game.house_fee_bps = MAX_HOUSE_FEE_BPS;
+            game.min_house_fee = MAX_MIN_HOUSE_FEE;
+            game.integrator_fee_bps = MAX_INTEGRATOR_FEE_BPS;
+        });
+
+        let house_fee = game.house_fee(pot).unwrap();
+        let integrator_fee = game.integrator_fee(pot, house_fee).unwrap();
+        assert_eq!(house_fee, pot / 10);
+        assert_eq!(integrator_fee, pot / 20);
+
+        // even a fee floor covering the whole pot leaves nothing to underflow
+        game.min_house_fee = pot;
+        let house_fee = game.house_fee(pot).unwrap();
+        assert_eq!(house_fee, pot);
+        assert_eq!(game.integrator_fee(pot, house_fee).unwrap(), 0);
+    }
+
+    #[test]
+    fn referral_reward_splits_rake_by_seat_deposit() {
+        let mut game = game_with(|game| {
+            game.referral_share_bps = 2_000;
+            game.deposit_p1 = 3 * MIN_BET_LAMPORTS;
+            game.deposit_p2 = MIN_BET_LAMPORTS;
+            game.total_pot = game.deposit_p1 + game.deposit_p2;
+        });
+
+        let house_fee = 40_000_000;
+        let p1 = game.referral_reward(house_fee, game.deposit_p1).unwrap();
+        let p2 = game.referral_reward(house_fee, game.deposit_p2).unwrap();
+        assert_eq!(p1, 6_000_000);
+        assert_eq!(p2, 2_000_000);
+        assert!(p1 + p2 <= house_fee);
+
+        game.total_pot = 0;
+        assert_eq!(game.referral_reward(house_fee, game.deposit_p1).unwrap(), 0);
+    }
+
+    #[test]
+    fn integrator_rewards_accrue_until_claimed() {
+        let mut rewards: IntegratorRewards = zeroed(IntegratorRewards::SPACE);
+        rewards.accrue(5_000_000).unwrap();
+        rewards.accrue(2_500_000).unwrap();
+        assert_eq!(rewards.claimable, 7_500_000);
+        assert_eq!(rewards.total_earned, 7_500_000);
+
+        rewards.claimable = u64::MAX;
+        assert!(rewards.accrue(1).is_err());
+    }
+
+    #[test]
+    fn withdrawals_respect_reserve_liabilities_and_epoch_cap() {
+        let mut vault: HouseVault = zeroed(HouseVault::SPACE);
+        vault.liabilities = 2_000;
+        assert_eq!(vault.withdrawable(10_000, 1_000), 7_000);
+        assert_eq!(vault.withdrawable(2_500, 1_000), 0);
+
+        assert_eq!(vault.remaining_withdrawal_cap(3), u64::MAX);
+        vault.withdrawal_cap_per_epoch = 5_000;
+        vault.record_withdrawal(3, 4_000).unwrap();
+        assert_eq!(vault.remaining_withdrawal_cap(3), 1_000);
+        // a new epoch starts with the full cap again
+        assert_eq!(vault.remaining_withdrawal_cap(4), 5_000);
+        vault.record_withdrawal(4, 500).unwrap();
+        assert_eq!(vault.remaining_withdrawal_cap(4), 4_500);
+        assert_eq!(vault.stats.withdrawals, 4_500);
+    }
+
+    #[test]
+    fn pay_seat_credits_balance_for_balance_staked_seats() {
+        let vault = account_info(Pubkey::new_unique(), crate::ID, 1_000, Vec::new());
+        let wallet = wallet(0);
+        let balance_info = program_account(
+            Pubkey::new_unique(),
+            &PlayerBalance {
+                bump: 255,
+                owner: wallet.key(),
+                amount: 0,
+            },
+            PlayerBalance::SPACE,
+        );
+        let rent_minimum = balance_info.lamports();
+        let system = system_program();
+        let mut balance = Some(Account::<PlayerBalance>::try_from(&*balance_info).unwrap());
+
+        pay_seat(300, true, wallet, &mut balance, vault, &system, &[]).unwrap();
+        assert_eq!(balance.as_ref().unwrap().amount, 300);
+        assert_eq!(balance_info.lamports(), rent_minimum + 300);
+        assert_eq!(wallet.lamports(), 0);
 
-    #[account(
-        mut,
-        seeds = [b"game", &game.game_id],
-        bump = game.bump,
-        constraint = player.key() == game.player1 || player.key() == game.player2
-            @ RpsError::NotAPlayer
-    )]
-    pub game: Account<'info, Game>,
-}
+        pay_seat(200, false, wallet, &mut balance, vault, &system, &[]).unwrap();
+        assert_eq!(wallet.lamports(), 200);
+        assert_eq!(vault.lamports(), 500);
 
-#[derive(Accounts)]
-pub struct AdminForceRefund<'info> {
-    /// Admin must be the house_vault admin
-    #[account(
-        mut,
-        address = house_vault.admin @ RpsError::Unauthorized
-    )]
-    pub admin: Signer<'info>,
+        // a balance-staked seat can't be refunded without its balance account
+        assert!(pay_seat(100, true, wallet, &mut None, vault, &system, &[]).is_err());
+    }
 
-    #[account(
-        seeds = [b"house_vault"],
-        bump = house_vault.bump,
-    )]
-    pub house_vault: Account<'info, HouseVault>,
+    #[test]
+    fn session_keys_act_for_their_seat_within_expiry_and_scope() {
+        let game = game_with(|game| {
+            game.player1 = Pubkey::new_unique();
+            game.player2 = Pubkey::new_unique();
+            game.session_p2 = Pubkey::new_unique();
+            game.session_expiry_p2 = 100;
+            game.session_perms_p2 = SESSION_PERM_COMMIT | SESSION_PERM_FORFEIT;
+        });
 
-    #[account(
-        mut,
-        close = player1,
-        seeds = [b"game", &game.game_id],
-        bump = game.bump
-    )]
-    pub game: Account<'info, Game>,
+        assert!(game.acts_as_player1(&game.player1, SESSION_PERM_FORFEIT, 0, None).unwrap());
+        assert!(!game.acts_as_player1(&game.player2, SESSION_PERM_FORFEIT, 0, None).unwrap());
+        // player2's session key forfeits player2's seat, not player1's
+        assert!(!game
+            .acts_as_player1(&game.session_p2, SESSION_PERM_FORFEIT, 100, None)
+            .unwrap());
+        assert!(game
+            .acts_as_player1(&game.session_p2, SESSION_PERM_FORFEIT, 101, None)
+            .is_err());
+        assert!(game
+            .acts_as_player1(&game.session_p2, SESSION_PERM_REVEAL, 50, None)
+            .is_err());
+        assert!(game
+            .acts_as_player1(&Pubkey::new_unique(), SESSION_PERM_FORFEIT, 0, None)
+            .is_err());
+    }
 
-    /// CHECK: Player1 receives the refund. Address verified against game.player1.
-    #[account(
-        mut,
-        address = game.player1 @ RpsError::InvalidPlayerAccount
-    )]
-    pub player1: AccountInfo<'info>,
+    /// Ed25519 verify-instruction data for `entries`, each signing its own message,
+    /// with every offset pointing into the instruction itself.
+    fn ed25519_data(entries: &[(Pubkey, &[u8])]) -> Vec<u8> {
+        let mut data = vec![entries.len() as u8, 0];
+        let mut body = Vec::new();
+        let body_start = 2 + entries.len() * 14;
+        for (key, message) in entries {
+            let public_key_offset = body_start + body.len();
+            body.extend_from_slice(key.as_ref());
+            let signature_offset = body_start + body.len();
+            body.extend_from_slice(&[0u8; 64]);
+            let message_offset = body_start + body.len();
+            body.extend_from_slice(message);
+            for field in [
+                signature_offset as u16,
+                u16::MAX,
+                public_key_offset as u16,
+                u16::MAX,
+                message_offset as u16,
+                message.len() as u16,
+                u16::MAX,
+            ] {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        data.extend_from_slice(&body);
+        data
+    }
 
-    /// CHECK: Game vault PDA holding the bet. Address enforced via seeds + bump.
-    #[account(
-        mut,
-        seeds = [b"game_vault", &game.game_id],
-        bump,
-        owner = system_program::ID
-    )]
-    pub game_vault: UncheckedAccount<'info>,
+    #[test]
+    fn ed25519_parser_returns_every_signer_of_the_message() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = ed25519_data(&[(a, b"state"), (b, b"state")]);
+        assert_eq!(ed25519_signers(&data, b"state").unwrap(), vec![a, b]);
+    }
 
-    pub system_program: Program<'info, System>,
-}
+    #[test]
+    fn ed25519_parser_rejects_a_wrong_message() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(ed25519_signers(&ed25519_data(&[(a, b"other")]), b"state").is_err());
+        // every signature has to cover the expected message
+        let data = ed25519_data(&[(a, b"state"), (b, b"other")]);
+        assert!(ed25519_signers(&data, b"state").is_err());
+    }
 
-#[derive(Accounts)]
-pub struct CancelGameIfTimedOut<'info> {
-    #[account(mut)]
-    pub player1: Signer<'info>,
+    #[test]
+    fn ed25519_parser_rejects_offsets_outside_the_instruction() {
+        let data = ed25519_data(&[(Pubkey::new_unique(), b"state")]);
 
-    #[account(
-        mut,
-        close = player1,
-        seeds = [b"game", &game.game_id],
-        bump = game.bump
-    )]
-    pub game: Account<'info, Game>,
+        let mut key_out_of_bounds = data.clone();
+        let past_end = (data.len() - 16) as u16;
+        key_out_of_bounds[6..8].copy_from_slice(&past_end.to_le_bytes());
+        assert!(ed25519_signers(&key_out_of_bounds, b"state").is_err());
 
-    /// CHECK: This is the PDA vault holding the game pot. Address is enforced via seeds and bump,
-    /// and we only move lamports from it (no data layout is assumed).
-    #[account(
-        mut,
-        seeds = [b"game_vault", &game.game_id],
-        bump,
-        owner = system_program::ID
-    )]
-    pub game_vault: UncheckedAccount<'info>,
+        let mut message_out_of_bounds = data.clone();
+        message_out_of_bounds[12..14].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(ed25519_signers(&message_out_of_bounds, b"state").is_err());
 
-    pub system_program: Program<'info, System>,
-}
+        // more signatures claimed than offsets records present
+        let mut truncated = data.clone();
+        truncated[0] = 9;
+        assert!(ed25519_signers(&truncated, b"state").is_err());
+        assert!(ed25519_signers(&[], b"state").is_err());
+    }
 
-#[derive(Accounts)]
-pub struct SetHouseFee<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
+    #[test]
+    fn ed25519_parser_rejects_data_from_other_instructions() {
+        let data = ed25519_data(&[(Pubkey::new_unique(), b"state")]);
+        // signature, public key and message instruction indexes
+        for at in [4, 8, 14] {
+            let mut elsewhere = data.clone();
+            elsewhere[at..at + 2].copy_from_slice(&0u16.to_le_bytes());
+            assert!(ed25519_signers(&elsewhere, b"state").is_err());
+        }
+    }
 
-    #[account(
-        mut,
-        seeds = [b"house_vault"],
-        bump = house_vault.bump,
-    )]
-    pub house_vault: Account<'info, HouseVault>,
-}
+    #[test]
+    fn ed25519_signers_need_a_preceding_verify_instruction() {
+        use anchor_lang::solana_program::instruction::BorrowedInstruction;
+        use solana_instructions_sysvar::{construct_instructions_data, store_current_index_checked};
+
+        let signer = Pubkey::new_unique();
+        let verify_data = ed25519_data(&[(signer, b"state")]);
+        let sysvar_key = solana_sdk_ids::sysvar::instructions::ID;
+        let instructions_for = |program_ids: &[Pubkey], current: u16| {
+            let borrowed: Vec<_> = program_ids
+                .iter()
+                .map(|program_id| BorrowedInstruction {
+                    program_id,
+                    accounts: Vec::new(),
+                    data: &verify_data,
+                })
+                .collect();
+            let mut data = construct_instructions_data(&borrowed);
+            store_current_index_checked(&mut data, current).unwrap();
+            data
+        };
+        let signers = |data: Vec<u8>| {
+            verified_ed25519_signer(account_info(sysvar_key, sysvar_key, 0, data), b"state")
+        };
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-pub enum GameStatus {
-    WaitingForPlayer2 = 0,
-    Active = 1,
-    Finished = 2,
-    Cancelled = 3,
-    Settled = 4, // payouts done, cannot be settled again
-}
+        assert_eq!(
+            signers(instructions_for(&[ed25519_program::ID, crate::ID], 1)).unwrap(),
+            signer
+        );
+        // nothing before the current instruction
+        assert!(signers(instructions_for(&[crate::ID], 0)).is_err());
+        // the preceding instruction isn't the Ed25519 program
+        assert!(signers(instructions_for(&[crate::ID, crate::ID], 1)).is_err());
+    }
 
-#[account]
-pub struct HouseVault {
-    pub bump: u8,
-    pub admin: Pubkey,      // who is allowed to withdraw / change fee
-    pub house_fee_bps: u16, // current global fee configuration
-}
+    fn channel_state(seq: u64, rounds_played: u8, player1_wins: u8, player2_wins: u8)
+        -> ChannelState
+    {
+        ChannelState {
+            seq,
+            rounds_played,
+            player1_wins,
+            player2_wins,
+        }
+    }
 
-impl HouseVault {
-    pub const SPACE: usize = 8 // discriminator
-        + 1                    // bump
-        + 32                   // admin
-        + 2;                   // house_fee_bps
-}
+    #[test]
+    fn channel_states_must_be_newer_than_the_last_accepted() {
+        let mut game = game_with(|game| game.status = GameStatus::Active);
+        game.apply_channel_state(&channel_state(5, 2, 1, 1), 1_000).unwrap();
 
-#[account]
-pub struct Game {
-    pub bump: u8,
-    pub game_id: [u8; 32],
+        assert!(game.apply_channel_state(&channel_state(5, 3, 2, 1), 1_010).is_err());
+        assert!(game.apply_channel_state(&channel_state(4, 3, 2, 1), 1_010).is_err());
+        // a newer seq still can't roll back the score
+        assert!(game.apply_channel_state(&channel_state(6, 2, 0, 1), 1_010).is_err());
+        assert_eq!((game.channel_seq, game.player1_wins), (5, 1));
+    }
 
-    pub player1: Pubkey,
-    pub player2: Pubkey,
-    pub house_vault: Pubkey,
+    #[test]
+    fn newer_channel_state_restarts_the_challenge_window() {
+        let mut game = game_with(|game| game.status = GameStatus::Active);
+        game.apply_channel_state(&channel_state(1, 1, 1, 0), 1_000).unwrap();
+        assert_eq!(game.channel_challenge_deadline, 1_000 + CHANNEL_CHALLENGE_SLOTS);
+
+        // replaced on the last slot of the window: the answer gets a full window too
+        let last_slot = game.channel_challenge_deadline;
+        game.apply_channel_state(&channel_state(2, 2, 1, 1), last_slot).unwrap();
+        assert_eq!(game.channel_challenge_deadline, last_slot + CHANNEL_CHALLENGE_SLOTS);
+        assert_eq!(game.rounds_played, 2);
+        assert!(game.round_resolved[..2].iter().all(|resolved| *resolved));
+
+        // once the window lapses, play is back on-chain
+        let closed = game.channel_challenge_deadline + 1;
+        assert!(game.apply_channel_state(&channel_state(3, 3, 2, 1), closed).is_err());
+
+        // a final state ends the match instead of opening another window
+        game.apply_channel_state(&channel_state(3, 4, 3, 1), last_slot + 1).unwrap();
+        assert!(game.status == GameStatus::Finished);
+    }
 
-    pub session_p1: Pubkey, // delegated signer that can act as player1
-    pub session_p2: Pubkey, // delegated signer that can act as player2
+    /// Root of a match tree over `leaves` and the proof for slot `index`.
+    fn match_tree(leaves: &[[u8; 32]], index: usize) -> ([u8; 32], Vec<[u8; 32]>) {
+        let mut level = leaves.to_vec();
+        level.resize(1 << MATCH_TREE_DEPTH, [0u8; 32]);
+        let mut proof = Vec::new();
+        let mut position = index;
+        while level.len() > 1 {
+            proof.push(level[position ^ 1]);
+            level = level
+                .chunks(2)
+                .map(|pair| merkle_node(&pair[0], &pair[1]))
+                .collect();
+            position /= 2;
+        }
+        (level[0], proof)
+    }
 
-    pub bet_amount: u64,
-    pub entry_fee: u64,
-    pub total_pot: u64,
-    pub house_fee_bps: u16,
+    #[test]
+    fn match_proof_binds_each_leaf_to_its_round_slot() {
+        let (game_id, player) = ([7u8; 32], Pubkey::new_unique());
+        let leaves: Vec<_> = (0..MAX_ROUNDS as u8)
+            .map(|round| move_commitment(round % 3 + 1, &[round; 32], &game_id, round, &player))
+            .collect();
+
+        for (round, leaf) in leaves.iter().enumerate() {
+            let (root, proof) = match_tree(&leaves, round);
+            assert_eq!(proof.len(), MATCH_TREE_DEPTH);
+            assert_eq!(merkle_root(*leaf, round as u8, &proof), root);
+            // the same leaf and proof don't verify for another round's slot
+            let other = (round as u8 + 1) % MAX_ROUNDS as u8;
+            assert_ne!(merkle_root(*leaf, other, &proof), root);
+        }
+    }
 
-    pub rounds_played: u8,
-    pub player1_wins: u8,
-    pub player2_wins: u8,
-    pub status: GameStatus,
+    #[test]
+    fn match_proof_rejects_a_second_leaf_for_the_same_round() {
+        let (game_id, player) = ([7u8; 32], Pubkey::new_unique());
+        let rock = move_commitment(1, &[1u8; 32], &game_id, 0, &player);
+        let paper = move_commitment(2, &[2u8; 32], &game_id, 0, &player);
+
+        // round 0's alternative move hidden in round 1's slot, or in a spare slot
+        for slot in [1, MAX_ROUNDS] {
+            let mut leaves = vec![[0u8; 32]; 1 << MATCH_TREE_DEPTH];
+            leaves[0] = rock;
+            leaves[slot] = paper;
+            let (root, rock_proof) = match_tree(&leaves, 0);
+            let (_, paper_proof) = match_tree(&leaves, slot);
+
+            assert_eq!(merkle_root(rock, 0, &rock_proof), root);
+            assert_ne!(merkle_root(paper, 0, &paper_proof), root);
+            assert_ne!(merkle_root(paper, 0, &rock_proof), root);
+        }
+    }
 
-    pub created_slot: u64, // for timeout logic
+    #[test]
+    fn program_owned_vaults_pay_out_by_moving_lamports_directly() {
+        let vault = account_info(Pubkey::new_unique(), crate::ID, 1_000, Vec::new());
+        let to = wallet(5);
+        let system = system_program();
+
+        // no signer seeds and no system program CPI needed
+        pay_from_vault(400, vault, to, &system, &[]).unwrap();
+        assert_eq!((vault.lamports(), to.lamports()), (600, 405));
+        pay_from_vault(0, vault, to, &system, &[]).unwrap();
+        assert!(pay_from_vault(601, vault, to, &system, &[]).is_err());
+        assert_eq!((vault.lamports(), to.lamports()), (600, 405));
+    }
 
-    // per-round commit / reveal data
-    pub commitments_p1: [[u8; 32]; MAX_ROUNDS],
-    pub commitments_p2: [[u8; 32]; MAX_ROUNDS],
-    pub committed_p1: [bool; MAX_ROUNDS],
-    pub committed_p2: [bool; MAX_ROUNDS],
-    pub moves_p1: [u8; MAX_ROUNDS],
-    pub moves_p2: [u8; MAX_ROUNDS],
-    pub revealed_p1: [bool; MAX_ROUNDS],
-    pub revealed_p2: [bool; MAX_ROUNDS],
-    pub commit_deadline_slots: [u64; MAX_ROUNDS],
-    //pub reveal_deadline_slots: [u64; MAX_ROUNDS],
-    // koray-27.11.2025: to prevent double-resolution / reveals after timeout
-    pub round_resolved: [bool; MAX_ROUNDS],
-}
+    #[test]
+    fn closed_game_vault_is_emptied_and_returned_to_the_system_program() {
+        let vault = account_info(Pubkey::new_unique(), crate::ID, 890_880 + 200, Vec::new());
+        let player1 = wallet(0);
+        let system = system_program();
+
+        // escrow not fully paid out yet
+        assert!(require_escrow_released(200, 200, vault).is_err());
+
+        pay_from_vault(200, vault, player1, &system, &[]).unwrap();
+        close_vault(vault, player1, &system, &[]).unwrap();
+        assert_eq!(vault.lamports(), 0);
+        assert_eq!(player1.lamports(), 890_880 + 200);
+        assert_eq!(vault.owner, &system_program::ID);
+        require_escrow_released(200, 200, vault).unwrap();
+        assert!(require_escrow_released(100, 200, vault).is_err());
+    }
 
-impl Game {
-    pub const SPACE: usize = 8  // discriminator
-        + 1                     // bump
-        + 32                    // game_id
-        + 32 * 3                // player1, player2, house_vault
-        + 32 * 2                // session_p1, session_p2
-        + 8 * 3                 // bet_amount, entry_fee, total_pot
-        + 2                     // house_fee_bps
-        + 1 * 4                 // rounds_played, p1_wins, p2_wins, status (u8)
-        + 8                     // created_at
-        + (32 * MAX_ROUNDS) * 2 // commitments_p1, commitments_p2
-        + (1 * MAX_ROUNDS) * 2  // committed_p1, committed_p2
-        + (1 * MAX_ROUNDS) * 2  // moves_p1, moves_p2
-        + (1 * MAX_ROUNDS) * 2  // revealed_p1, revealed_p2
-        + (8 * MAX_ROUNDS)      // commit_deadline_slots
-        + (1 * MAX_ROUNDS);     // round_resolved
-}
+    #[test]
+    fn sweep_moves_shard_balances_above_rent_into_the_house_vault() {
+        let admin = Pubkey::new_unique();
+        let house_vault = house_vault_with(|vault| vault.admin = admin);
+        let vault_sol = house_vault_sol(0);
+        let shards = [0, 5, 7].map(|index| fee_shard(index, 0, HouseStats::default()));
+        **shards[0].lamports.borrow_mut() += 3_000;
+        **shards[2].lamports.borrow_mut() += 1_500;
+        let sweep = |caller: &AccountInfo<'static>, shards: &[&AccountInfo<'static>]| {
+            let mut accounts = vec![caller, &*house_vault, &*vault_sol];
+            accounts.extend_from_slice(shards);
+            process(crate::instruction::SweepFeeShards {}, &accounts)
+        };
 
+        assert_eq!(
+            sweep(signer(Pubkey::new_unique()), &[&*shards[0]]),
+            Err(program_error(RpsError::Unauthorized))
+        );
+        // a shard account at another shard's address
+        let (_, bump) = pda(&[b"fee_shard", &[5]]);
+        let misplaced = program_account(
+            shards[0].key(),
+            &FeeShard { bump, index: 5, stats: HouseStats::default() },
+            FeeShard::SPACE,
+        );
+        assert_eq!(
+            sweep(signer(admin), &[misplaced]),
+            Err(program_error(RpsError::InvalidFeeShard))
+        );
 
-// ---------- Events ----------
+        sweep(signer(admin), &[&*shards[0], &*shards[1], &*shards[2]]).unwrap();
+        assert_eq!(above_rent(vault_sol), 4_500);
+        assert!(shards.iter().all(|shard| above_rent(shard) == 0));
+        // nothing left to move the second time round
+        sweep(signer(admin), &[&*shards[0], &*shards[2]]).unwrap();
+        assert_eq!(above_rent(vault_sol), 4_500);
+    }
 
-#[event]
-pub struct RoundPhaseEvent {
-    pub game_id: [u8; 32],
-    pub round: u8,
-    pub current_slot: u64,
-    pub commit_deadline_slot: u64,
-    pub reveal_deadline_slot: u64,
-    pub both_committed: bool,
-}
+    #[test]
+    fn admin_transfer_needs_the_nominee_to_accept() {
+        let mut vault: HouseVault = zeroed(HouseVault::SPACE);
+        let (admin, nominee) = (Pubkey::new_unique(), Pubkey::new_unique());
+        vault.admin = admin;
+
+        // nobody nominated yet
+        assert!(vault.accept_pending_admin(&nominee).is_err());
+        assert!(vault.accept_pending_admin(&Pubkey::default()).is_err());
+
+        vault.pending_admin = nominee;
+        assert!(vault.accept_pending_admin(&Pubkey::new_unique()).is_err());
+        assert_eq!(vault.admin, admin);
+
+        assert_eq!(vault.accept_pending_admin(&nominee).unwrap(), admin);
+        assert_eq!(vault.admin, nominee);
+        assert_eq!(vault.pending_admin, Pubkey::default());
+        // a nomination is used up once accepted
+        assert!(vault.accept_pending_admin(&nominee).is_err());
+    }
 
-#[event]
-pub struct RoundResultEvent {
-    pub game_id: [u8; 32],
-    pub round: u8,
-    pub player1_wins: u8,
-    pub player2_wins: u8,
-    pub rounds_played: u8,
-    pub status: GameStatus,
-}
+    #[test]
+    fn role_assignments_grant_only_their_roles() {
+        let mut assignment: RoleAssignment = zeroed(RoleAssignment::SPACE);
+        let roles = [Role::FeeManager, Role::Treasurer, Role::Pauser, Role::Refunder];
+        assert!(roles.iter().all(|role| !assignment.has(*role)));
 
-#[event]
-pub struct RoundStartEvent {
-    pub game_id: [u8; 32],
-    pub round: u8,
-    pub start_slot: u64,
-    pub commit_deadline_slot: u64,
-}
+        assignment.roles = Role::Treasurer.mask() | Role::Pauser.mask();
+        assert!(assignment.has(Role::Treasurer) && assignment.has(Role::Pauser));
+        assert!(!assignment.has(Role::FeeManager) && !assignment.has(Role::Refunder));
 
-#[event]
-pub struct GameForfeitEvent {
-    pub game_id: [u8; 32],
-    pub loser: Pubkey,
-    pub winner: Pubkey,
-}
+        // a withdrawal proposal needs the treasurer, an admin set change no role
+        let withdraw = ProposalAction::WithdrawHouseFunds {
+            amount: 1,
+            recipient: Pubkey::new_unique(),
+        };
+        assert!(withdraw.required_role() == Some(Role::Treasurer));
+        let admin_set = ProposalAction::UpdateAdminSet {
+            admins: [Pubkey::default(); MAX_ADMINS],
+            admin_count: 0,
+            threshold: 0,
+        };
+        assert!(admin_set.required_role().is_none());
+    }
 
-#[event]
-pub struct GameCancelledEvent {
-    pub game_id: [u8; 32],
-    pub player1: Pubkey,
-    pub player2: Pubkey,
-    pub player1_refund: u64,
-    pub player2_refund: u64,
-}
+    #[test]
+    fn circuit_breakers_block_only_their_operations() {
+        let mut vault: HouseVault = zeroed(HouseVault::SPACE);
+        for op in [PAUSE_CREATE_GAME, PAUSE_JOIN_GAME, PAUSE_START_ROUND] {
+            require_not_paused(&vault, op).unwrap();
+        }
 
-#[event]
-pub struct AdminForceRefundEvent {
-    pub game_id: [u8; 32],
-    pub player1: Pubkey,
-    pub refund_amount: u64,
-    pub admin: Pubkey,
-}
+        vault.paused_ops = PAUSE_CREATE_GAME;
+        assert!(require_not_paused(&vault, PAUSE_CREATE_GAME).is_err());
+        require_not_paused(&vault, PAUSE_JOIN_GAME).unwrap();
+        require_not_paused(&vault, PAUSE_START_ROUND).unwrap();
 
-// ---------- Instruction Contexts ----------
+        // the global switch trips every breaker
+        vault.paused_ops = PAUSABLE_OPS;
+        for op in [PAUSE_CREATE_GAME, PAUSE_JOIN_GAME, PAUSE_START_ROUND] {
+            assert!(require_not_paused(&vault, op).is_err());
+        }
+    }
 
-#[derive(Accounts)]
-pub struct InitHouseVault<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
+    #[test]
+    fn force_refund_repays_both_seats_of_games_still_in_escrow() {
+        let mut game = game_with(|game| {
+            game.deposit_p1 = 3 * MIN_BET_LAMPORTS;
+            game.deposit_p2 = MIN_BET_LAMPORTS;
+            game.fee_deposit_p1 = 1_000;
+            game.fee_deposit_p2 = 2_000;
+        });
 
-    #[account(
-        init,
-        payer = admin,
-        space = HouseVault::SPACE,
-        seeds = [b"house_vault"],
-        bump
-    )]
-    pub house_vault: Account<'info, HouseVault>,
+        for status in [GameStatus::WaitingForPlayer2, GameStatus::Active, GameStatus::Finished] {
+            game.status = status;
+            assert_eq!(
+                game.force_refund_amounts(true).unwrap(),
+                [(3 * MIN_BET_LAMPORTS, 1_000), (MIN_BET_LAMPORTS, 2_000)]
+            );
+            assert_eq!(
+                game.force_refund_amounts(false).unwrap(),
+                [(3 * MIN_BET_LAMPORTS, 0), (MIN_BET_LAMPORTS, 0)]
+            );
+        }
 
-    /// CHECK: PDA used as the on-chain SOL vault for house fees. Created and constrained by
-    /// seeds + bump, only used as a lamport vault, never deserialized.
-    #[account(
-        init,
-        payer = admin,
-        space = 0,
-        seeds = [b"house_vault_sol"],
-        bump,
-        owner = system_program::ID
-    )]
-    pub house_vault_sol: UncheckedAccount<'info>,
+        // deposits of cancelled games were already paid back
+        game.status = GameStatus::Cancelled;
+        assert!(game.force_refund_amounts(true).is_err());
+    }
+
+    #[test]
+    fn pending_house_fee_applies_only_after_its_timelock() {
+        let mut vault: HouseVault = zeroed(HouseVault::SPACE);
+        vault.house_fee_bps = 100;
+        vault.pending_house_fee_bps = 300;
+        vault.pending_fee_activation_slot = 1_000 + HOUSE_FEE_TIMELOCK_SLOTS;
+
+        let activation = vault.pending_fee_activation_slot;
+        assert_eq!(vault.effective_house_fee_bps(activation - 1), 100);
+        assert_eq!(vault.effective_house_fee_bps(activation), 300);
+
+        vault.apply_pending_house_fee(activation - 1);
+        assert_eq!(vault.house_fee_bps, 100);
+        assert_eq!(vault.pending_fee_activation_slot, activation);
+
+        vault.apply_pending_house_fee(activation);
+        assert_eq!(vault.house_fee_bps, 300);
+        assert_eq!(vault.pending_fee_activation_slot, 0);
+        // nothing pending any more
+        assert_eq!(vault.effective_house_fee_bps(u64::MAX), 300);
+    }
+
+    #[test]
+    fn rake_tiers_pick_the_highest_tier_a_bet_reaches() {
+        let mut schedule = RakeSchedule::default();
+        schedule.tiers[0] = RakeTier { min_bet: MIN_BET_LAMPORTS * 10, fee_bps: 80 };
+        schedule.tiers[1] = RakeTier { min_bet: MIN_BET_LAMPORTS * 100, fee_bps: 50 };
+        schedule.tier_count = 2;
+        schedule.validate().unwrap();
+
+        assert_eq!(schedule.fee_bps_for(MIN_BET_LAMPORTS, 100), 100);
+        assert_eq!(schedule.fee_bps_for(MIN_BET_LAMPORTS * 10, 100), 80);
+        assert_eq!(schedule.fee_bps_for(MIN_BET_LAMPORTS * 99, 100), 80);
+        assert_eq!(schedule.fee_bps_for(MIN_BET_LAMPORTS * 1_000, 100), 50);
+
+        // tiers must ascend, and unused slots stay empty
+        schedule.tiers.swap(0, 1);
+        assert!(schedule.validate().is_err());
+        schedule.tiers.swap(0, 1);
+        schedule.tier_count = 1;
+        assert!(schedule.validate().is_err());
+    }
 
-    pub system_program: Program<'info, System>,
-}
+    #[test]
+    fn payee_splits_must_cover_the_whole_distribution_once() {
+        let (treasury, dev) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut split = PayeeSplit::default();
+        split.payees[0] = Payee { recipient: treasury, share_bps: 7_000 };
+        split.payees[1] = Payee { recipient: dev, share_bps: 3_000 };
+        split.payee_count = 2;
+        split.validate().unwrap();
+
+        // shares short of 100%
+        let mut short = split;
+        short.payees[1].share_bps = 2_000;
+        assert!(short.validate().is_err());
+
+        // the same recipient twice
+        let mut duplicate = split;
+        duplicate.payees[1].recipient = treasury;
+        assert!(duplicate.validate().is_err());
+
+        // a payee beyond `payee_count`
+        let mut stray = split;
+        stray.payees[2] = Payee { recipient: Pubkey::new_unique(), share_bps: 1 };
+        assert!(stray.validate().is_err());
+
+        assert!(PayeeSplit::default().validate().is_err());
+    }
 
-#[derive(Accounts)]
-#[instruction(game_id: [u8; 32])]
-pub struct CreateGame<'info> {
-    #[account(mut)]
-    pub player1: Signer<'info>,
+    #[test]
+    fn anyone_can_distribute_house_funds_up_to_the_epoch_cap() {
+        let (treasury, dev) = (wallet(0), wallet(0));
+        let house_vault = house_vault_with(|vault| {
+            vault.payee_split.payees[0] = Payee { recipient: treasury.key(), share_bps: 7_000 };
+            vault.payee_split.payees[1] = Payee { recipient: dev.key(), share_bps: 3_000 };
+            vault.payee_split.payee_count = 2;
+            vault.withdrawal_cap_per_epoch = 1_000;
+        });
+        let vault_sol = house_vault_sol(5_000);
+        let system = system_program();
+        let distribute = || {
+            process(
+                crate::instruction::DistributeHouseFunds {},
+                &[
+                    signer(Pubkey::new_unique()),
+                    house_vault,
+                    vault_sol,
+                    system.as_ref(),
+                    treasury,
+                    dev,
+                ],
+            )
+            .unwrap()
+        };
 
-    #[account(
-        mut,
-        seeds = [b"house_vault"],
-        bump = house_vault.bump
-    )]
-    pub house_vault: Account<'info, HouseVault>,
+        set_clock(100, 7);
+        distribute();
+        assert_eq!((treasury.lamports(), dev.lamports()), (700, 300));
+        // the cap is spent for this epoch, whoever calls
+        distribute();
+        assert_eq!((treasury.lamports(), dev.lamports()), (700, 300));
+
+        set_clock(500_000, 8);
+        distribute();
+        assert_eq!((treasury.lamports(), dev.lamports()), (1_400, 600));
+        assert_eq!(above_rent(vault_sol), 3_000);
+        assert_eq!(load::<HouseVault>(house_vault).stats.withdrawals, 2_000);
+    }
 
-    /// CHECK: House SOL vault PDA. We verify its address with seeds + bump and only use it
-    /// as the recipient of entry fees (lamport transfers only).
-    #[account(
-        mut,
-        seeds = [b"house_vault_sol"],
-        bump,
-        owner = system_program::ID
-    )]
-    pub house_vault_sol: UncheckedAccount<'info>,
+    #[test]
+    fn players_can_only_forfeit_their_own_seat() {
+        set_clock(100, 0);
+        let forfeit = |caller: Pubkey, game_info, loser_is_player1| {
+            process(
+                crate::instruction::ForfeitGame { loser_is_player1 },
+                &[signer(caller), game_info],
+            )
+        };
+        let (player1, player2) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (game_info, _) = game_accounts(game_with(|game| {
+            game.status = GameStatus::Active;
+            game.player1 = player1;
+            game.player2 = player2;
+        }));
+
+        assert_eq!(
+            forfeit(player2, game_info, true),
+            Err(program_error(RpsError::ForfeitForOtherSeat))
+        );
+        assert_eq!(
+            forfeit(player1, game_info, false),
+            Err(program_error(RpsError::ForfeitForOtherSeat))
+        );
+        forfeit(player2, game_info, false).unwrap();
+        let game: Game = load(game_info);
+        assert!(game.status == GameStatus::Finished);
+        assert_eq!((game.player1_wins, game.player2_wins), (3, 0));
+    }
 
-    #[account(
-        init,
-        payer = player1,
-        space = Game::SPACE,
-        seeds = [b"game", game_id.as_ref()],
-        bump
-    )]
-    pub game: Account<'info, Game>,
+    #[test]
+    fn referral_pda_yields_the_registered_referrer_if_any() {
+        let (player, referrer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (key, bump) = pda(&[b"referral", player.as_ref()]);
 
-    /// CHECK: Per-game pot vault PDA. Address is derived via seeds + bump and only holds lamports.
-    #[account(
-        init,
-        payer = player1,
-        space = 0,
-        seeds = [b"game_vault", game_id.as_ref()],
-        bump,
-        owner = system_program::ID
-    )]
-    pub game_vault: UncheckedAccount<'info>,
+        let unregistered = account_info(key, system_program::ID, 0, Vec::new());
+        assert_eq!(registered_referrer(unregistered).unwrap(), Pubkey::default());
 
-    pub system_program: Program<'info, System>,
-}
+        let referral = Referral { bump, player, referrer };
+        let registered = program_account(key, &referral, Referral::SPACE);
+        assert_eq!(registered_referrer(registered).unwrap(), referrer);
+    }
 
-#[derive(Accounts)]
-pub struct JoinGame<'info> {
-    #[account(mut)]
-    pub player2: Signer<'info>,
+    #[test]
+    fn epoch_report_adds_shard_and_vault_counters_up_to_house_totals() {
+        set_clock(9_000, 12);
+        let house_vault = house_vault_with(|vault| vault.stats.withdrawals = 3_000);
+        // 5_000 of shard 2's fees and rake were swept before the withdrawals
+        let vault_sol = house_vault_sol(2_000);
+
+        let mut busy = HouseStats::default();
+        busy.record_game_created().unwrap();
+        busy.record_deposit(MIN_BET_LAMPORTS, 1_000).unwrap();
+        busy.record_deposit(MIN_BET_LAMPORTS, 1_000).unwrap();
+        busy.record_settlement(4_000).unwrap();
+        let mut refunded = HouseStats::default();
+        refunded.record_game_created().unwrap();
+        refunded.record_deposit(MIN_BET_LAMPORTS, 500).unwrap();
+        refunded.record_bet_refund(MIN_BET_LAMPORTS).unwrap();
+        refunded.record_entry_fee_refund(250).unwrap();
+
+        let shards: Vec<_> = (0..FEE_SHARD_COUNT)
+            .map(|index| match index {
+                2 => fee_shard(index, 1_000, busy),
+                6 => fee_shard(index, 250, refunded),
+                _ => fee_shard(index, 0, HouseStats::default()),
+            })
+            .collect();
+
+        // `init` needs a system program CPI, so the handler runs on a pre-allocated report
+        let report_for = |epoch: u64, shards: &[&AccountInfo<'static>]| {
+            let (key, bump) = pda(&[b"epoch_report", &epoch.to_le_bytes()]);
+            let report = account_info(key, crate::ID, 0, vec![0; EpochReport::SPACE]);
+            let mut accounts = RecordEpochReport {
+                payer: Signer::try_from(&*signer(Pubkey::new_unique())).unwrap(),
+                house_vault: Account::try_from(&*house_vault).unwrap(),
+                house_vault_sol: UncheckedAccount::try_from(&*vault_sol),
+                epoch_report: Account::try_from_unchecked(&*report).unwrap(),
+                system_program: system_program(),
+            };
+            let remaining: Vec<_> = shards.iter().map(|shard| (*shard).clone()).collect();
+            let bumps = RecordEpochReportBumps { epoch_report: bump, ..Default::default() };
+            let ctx = Context::new(
+                &crate::ID,
+                &mut accounts,
+                Box::leak(remaining.into_boxed_slice()),
+                bumps,
+            );
+            rps_game::record_epoch_report(ctx, epoch).map(|_| accounts.epoch_report.clone())
+        };
 
-    #[account(
-        mut,
-        seeds = [b"game", &game.game_id],
-        bump = game.bump,
-        constraint = game.player1 != Pubkey::default() @ RpsError::InvalidGameState
-    )]
-    pub game: Account<'info, Game>,
+        let all: Vec<&AccountInfo<'static>> = shards.iter().map(|shard| &**shard).collect();
+        assert_eq!(report_for(11, &all).err(), Some(RpsError::InvalidEpoch.into()));
+        // one shard counted twice instead of another
+        let mut doubled = all.clone();
+        doubled[1] = doubled[2];
+        assert_eq!(report_for(12, &doubled).err(), Some(RpsError::InvalidFeeShard.into()));
+        assert_eq!(report_for(12, &all[1..]).err(), Some(RpsError::InvalidFeeShard.into()));
+
+        let report = report_for(12, &all).unwrap();
+        let totals = report.stats;
+        assert_eq!((report.epoch, report.slot), (12, 9_000));
+        assert_eq!((totals.games_created, totals.games_settled), (2, 1));
+        assert_eq!(totals.volume_wagered, 3 * MIN_BET_LAMPORTS);
+        assert_eq!(totals.entry_fees_collected, 2_500);
+        assert_eq!(totals.rake_collected, 4_000);
+        assert_eq!(totals.bets_refunded, MIN_BET_LAMPORTS);
+        assert_eq!(totals.entry_fees_refunded, 250);
+        assert_eq!(totals.withdrawals, 3_000);
+        assert_eq!((report.fee_shard_balance, report.house_vault_balance), (1_250, 2_000));
+        // balances match the counters: fees and rake in, refunds and withdrawals out
+        assert_eq!(
+            report.fee_shard_balance + report.house_vault_balance,
+            totals.entry_fees_collected + totals.rake_collected
+                - totals.entry_fees_refunded
+                - totals.withdrawals
+        );
+    }
 
-    /// CHECK: Same per-game pot PDA created in `CreateGame`. Address checked via seeds + bump.
-    #[account(
-        mut,
-        seeds = [b"game_vault", &game.game_id],
-        bump,
-        owner = system_program::ID
-    )]
-    pub game_vault: UncheckedAccount<'info>,
+    #[test]
+    fn escrow_audit_flags_vault_shortfalls_and_inconsistent_deposits() {
+        let rent = Rent::default();
+        let reserve = rent.minimum_balance(0);
+        let mut game = game_with(|game| {
+            game.status = GameStatus::Active;
+            game.player1 = Pubkey::new_unique();
+            game.player2 = Pubkey::new_unique();
+            game.deposit_p1 = MIN_BET_LAMPORTS;
+            game.deposit_p2 = MIN_BET_LAMPORTS;
+            game.total_pot = 2 * MIN_BET_LAMPORTS;
+        });
 
-    #[account(
-        seeds = [b"house_vault"],
-        bump = house_vault.bump,
-        constraint = house_vault.key() == game.house_vault @ RpsError::InvalidHouseWallet
-    )]
-    pub house_vault: Account<'info, HouseVault>,
+        let audit = |game: &Game, lamports: u64| {
+            let vault = account_info(Pubkey::new_unique(), crate::ID, lamports, Vec::new());
+            game.audit_escrow(vault, &rent)
+        };
 
-    /// CHECK: Global house SOL vault PDA, same as in `InitHouseVault`/`CreateGame`. Address enforced
-    /// via seeds + bump, used only for lamport transfers.
-    #[account(
-        mut,
-        seeds = [b"house_vault_sol"],
-        bump,
-        owner = system_program::ID
-    )]
-    pub house_vault_sol: UncheckedAccount<'info>,
+        let healthy = audit(&game, game.total_pot + reserve + 7);
+        assert!(healthy.healthy);
+        assert_eq!((healthy.rent_reserve, healthy.surplus), (reserve, 7));
 
-    pub system_program: Program<'info, System>,
-}
+        let short = audit(&game, game.total_pot + reserve - 1);
+        assert!(!short.healthy);
+        assert_eq!(short.shortfall, 1);
 
-#[derive(Accounts)]
-pub struct CommitMove<'info> {
-    #[account(
-        mut,
-        seeds = [b"game", &game.game_id],
-        bump = game.bump
-    )]
-    pub game: Account<'info, Game>,
+        game.deposit_p2 -= 1;
+        let mismatched = audit(&game, game.total_pot + reserve);
+        assert!(!mismatched.deposits_match_pot && !mismatched.healthy);
 
-    #[account(
-        mut,
-        constraint =
-            player.key() == game.player1 ||
-            player.key() == game.player2 ||
-            player.key() == game.session_p1 ||
-            player.key() == game.session_p2
-            @ RpsError::NotAPlayer
-    )]
-    pub player: Signer<'info>,
-}
+        // a settled game has nothing left in escrow
+        game.status = GameStatus::Settled;
+        assert!(audit(&game, 0).shortfall == 0);
+    }
 
-#[derive(Accounts)]
-pub struct RevealMove<'info> {
-    #[account(
-        mut,
-        seeds = [b"game", &game.game_id],
-        bump = game.bump
-    )]
-    pub game: Account<'info, Game>,
+    #[test]
+    fn seat_deposits_must_match_the_seats_taken() {
+        let rent = Rent::default();
+        let mut game = game_with(|game| {
+            game.status = GameStatus::WaitingForPlayer2;
+            game.player1 = Pubkey::new_unique();
+            game.deposit_p1 = MIN_BET_LAMPORTS;
+            game.total_pot = MIN_BET_LAMPORTS;
+        });
 
-    #[account(
-        mut,
-        constraint =
-            player.key() == game.player1 ||
-            player.key() == game.player2 ||
-            player.key() == game.session_p1 ||
-            player.key() == game.session_p2
-            @ RpsError::NotAPlayer
-    )]
-    pub player: Signer<'info>,
-}
+        let deposits_ok = |game: &Game| {
+            let lamports = game.total_pot + rent.minimum_balance(0);
+            let vault = account_info(Pubkey::new_unique(), crate::ID, lamports, Vec::new());
+            game.audit_escrow(vault, &rent).deposits_match_status
+        };
+        assert!(deposits_ok(&game));
 
+        // an open game can't hold a second seat's deposit
+        game.deposit_p2 = MIN_BET_LAMPORTS;
+        game.total_pot = 2 * MIN_BET_LAMPORTS;
+        assert!(!deposits_ok(&game));
 
-#[derive(Accounts)]
-pub struct SettleGame<'info> {
-    #[account(
-        mut,
-        close = player1, // <-- let Anchor close & refund rent to player1
-        seeds = [b"game", &game.game_id],
-        bump = game.bump
-    )]
-    pub game: Account<'info, Game>,
+        // once joined, both seats must have deposited
+        game.status = GameStatus::Active;
+        game.player2 = Pubkey::new_unique();
+        assert!(deposits_ok(&game));
+        game.deposit_p1 = 0;
+        game.total_pot = MIN_BET_LAMPORTS;
+        assert!(!deposits_ok(&game));
+    }
 
-    /// CHECK: safe because of the `address = game.player1` constraint
-    #[account(mut, address = game.player1 @ RpsError::InvalidPlayerAccount)]
-    pub player1: AccountInfo<'info>,
+    #[test]
+    fn wallet_sessions_act_for_their_owner_in_any_game() {
+        let owner = Pubkey::new_unique();
+        let session = WalletSession {
+            bump: 255,
+            owner,
+            session_key: Pubkey::new_unique(),
+            expiry_slot: 500,
+            permissions: WALLET_SESSION_PERMS,
+            max_bet: 2 * MIN_BET_LAMPORTS,
+        };
+        let key = session.session_key;
 
-    /// CHECK: safe because of the `address = game.player2` constraint
-    #[account(mut, address = game.player2 @ RpsError::InvalidPlayerAccount)]
-    pub player2: AccountInfo<'info>,
+        let mut game = game_with(|game| {
+            game.player1 = Pubkey::new_unique();
+            game.player2 = owner;
+            game.bet_amount = MIN_BET_LAMPORTS;
+        });
+        assert!(!game.acts_as_player1(&key, SESSION_PERM_COMMIT, 500, Some(&session)).unwrap());
+
+        // expired, never allowed to forfeit, or above the bet limit
+        assert!(game.acts_as_player1(&key, SESSION_PERM_COMMIT, 501, Some(&session)).is_err());
+        assert!(game.acts_as_player1(&key, SESSION_PERM_FORFEIT, 0, Some(&session)).is_err());
+        game.bet_amount = 3 * MIN_BET_LAMPORTS;
+        assert!(game.acts_as_player1(&key, SESSION_PERM_COMMIT, 0, Some(&session)).is_err());
+
+        // a game the owner doesn't play in
+        let other = game_with(|game| {
+            game.player1 = Pubkey::new_unique();
+            game.player2 = Pubkey::new_unique();
+        });
+        assert!(other.acts_as_player1(&key, SESSION_PERM_COMMIT, 0, Some(&session)).is_err());
+    }
 
-    #[account(
-        mut,
-        seeds = [b"house_vault"],
-        bump = house_vault.bump,
-        constraint = house_vault.key() == game.house_vault @ RpsError::InvalidHouseWallet
-    )]
-    pub house_vault: Account<'info, HouseVault>,
+    #[test]
+    fn cancel_refunds_swept_entry_fees_from_the_house_vault() {
+        let admin = Pubkey::new_unique();
+        let game = game_with(|game| {
+            game.game_id = [3u8; 32];
+            game.status = GameStatus::Active;
+            game.player1 = Pubkey::new_unique();
+            game.player2 = Pubkey::new_unique();
+            game.deposit_p1 = MIN_BET_LAMPORTS;
+            game.deposit_p2 = MIN_BET_LAMPORTS;
+            game.total_pot = 2 * MIN_BET_LAMPORTS;
+            game.fee_deposit_p1 = 1_000;
+            game.fee_deposit_p2 = 1_000;
+            game.fee_refund_policy.aborted_before_play_bps = 10_000;
+        });
+        let (game_info, game_vault) = game_accounts(game.clone());
+        let house_vault = house_vault_with(|vault| {
+            vault.admin = admin;
+            vault.liabilities = 500;
+        });
+        let vault_sol = house_vault_sol(0);
+        let shard = fee_shard(fee_shard_index(&game.game_id), 2_000, HouseStats::default());
 
-    /// CHECK: House fee SOL vault PDA; address enforced via seeds + bump, only used for lamports.
-    #[account(
-        mut,
-        seeds = [b"house_vault_sol"],
-        bump,
-        owner = system_program::ID
-    )]
-    pub house_vault_sol: UncheckedAccount<'info>,
+        process(
+            crate::instruction::SweepFeeShards {},
+            &[signer(admin), house_vault, vault_sol, shard],
+        )
+        .unwrap();
+        assert_eq!((above_rent(shard), above_rent(vault_sol)), (0, 2_000));
 
-    /// CHECK: Game pot SOL vault PDA; address enforced via seeds + bump, only used for lamports.
-    #[account(
-        mut,
-        seeds = [b"game_vault", &game.game_id],
-        bump,
-        owner = system_program::ID
-    )]
-    pub game_vault: UncheckedAccount<'info>,
+        let (player1, player2) = (
+            account_info(game.player1, system_program::ID, 0, Vec::new()),
+            account_info(game.player2, system_program::ID, 0, Vec::new()),
+        );
+        let vault_rent = game_vault.lamports() - game.total_pot;
+        let system = system_program();
+        process(
+            crate::instruction::CancelGame {},
+            &[
+                signer(Pubkey::new_unique()),
+                game_info,
+                player1,
+                player2,
+                game_vault,
+                shard,
+                house_vault,
+                vault_sol,
+                omitted(),
+                omitted(),
+                system.as_ref(),
+            ],
+        )
+        .unwrap();
+
+        // the house keeps its liabilities, so player2 is 500 short
+        assert_eq!(player1.lamports(), MIN_BET_LAMPORTS + 1_000 + vault_rent);
+        assert_eq!(player2.lamports(), MIN_BET_LAMPORTS + 500);
+        assert_eq!(above_rent(vault_sol), 500);
+        let shard_state: FeeShard = load(shard);
+        assert_eq!(shard_state.stats.entry_fees_refunded, 1_500);
+        assert!(load::<Game>(game_info).status == GameStatus::Cancelled);
+    }
 
-    pub system_program: Program<'info, System>,
-}
+    #[test]
+    fn force_refund_pays_swept_entry_fees_in_full_or_not_at_all() {
+        let force_refund = |liabilities: u64| {
+            let (admin, refunder, proposer) =
+                (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+            let game = game_with(|game| {
+                game.game_id = [4u8; 32];
+                game.status = GameStatus::Active;
+                game.player1 = Pubkey::new_unique();
+                game.player2 = Pubkey::new_unique();
+                game.deposit_p1 = MIN_BET_LAMPORTS;
+                game.deposit_p2 = MIN_BET_LAMPORTS;
+                game.total_pot = 2 * MIN_BET_LAMPORTS;
+                game.fee_deposit_p1 = 1_000;
+                game.fee_deposit_p2 = 1_000;
+            });
+            let (game_info, game_vault) = game_accounts(game.clone());
+            let house_vault = house_vault_with(|vault| {
+                vault.admin = admin;
+                vault.threshold = 1;
+                vault.liabilities = liabilities;
+            });
+            let vault_sol = house_vault_sol(300);
+            let shard = fee_shard(fee_shard_index(&game.game_id), 2_000, HouseStats::default());
+            process(
+                crate::instruction::SweepFeeShards {},
+                &[signer(admin), house_vault, vault_sol, shard],
+            )
+            .unwrap();
+
+            let (role_key, role_bump) = pda(&[b"role", refunder.as_ref()]);
+            let role = RoleAssignment {
+                bump: role_bump,
+                member: refunder,
+                roles: Role::Refunder.mask(),
+            };
+            let (proposal_key, proposal_bump) = pda(&[b"proposal", &7u64.to_le_bytes()]);
+            let proposal = Proposal {
+                bump: proposal_bump,
+                id: 7,
+                proposer,
+                action: ProposalAction::AdminForceRefund {
+                    game_id: game.game_id,
+                    refund_entry_fees: true,
+                },
+                admin_set_version: 0,
+                approvals: 1,
+                created_slot: 0,
+                expiry_slot: u64::MAX,
+            };
+            let (player1, player2) = (
+                account_info(game.player1, system_program::ID, 0, Vec::new()),
+                account_info(game.player2, system_program::ID, 0, Vec::new()),
+            );
+            let system = system_program();
+            let result = process(
+                crate::instruction::AdminForceRefund {},
+                &[
+                    signer(refunder),
+                    program_account(role_key, &role, RoleAssignment::SPACE),
+                    house_vault,
+                    program_account(proposal_key, &proposal, Proposal::SPACE),
+                    account_info(proposer, system_program::ID, 0, Vec::new()),
+                    game_info,
+                    player1,
+                    player2,
+                    game_vault,
+                    shard,
+                    vault_sol,
+                    omitted(),
+                    omitted(),
+                    system.as_ref(),
+                ],
+            );
+            (result, player2.lamports(), above_rent(vault_sol))
+        };
 
-// ---------- Errors ----------
+        // the swept fees come back out of house_vault_sol, above its liabilities
+        let (result, player2, house_left) = force_refund(300);
+        result.unwrap();
+        assert_eq!((player2, house_left), (MIN_BET_LAMPORTS + 1_000, 300));
 
-#[error_code]
-pub enum RpsError {
-    #[msg("Invalid bet amount")]
-    InvalidBetAmount,
-    #[msg("Invalid entry fee")]
-    InvalidEntryFee,
-    #[msg("Game is not joinable")]
-    GameNotJoinable,
-    #[msg("Game already has a second player")]
-    AlreadyHasPlayer2,
-    #[msg("Game is not active")]
-    GameNotActive,
-    #[msg("Game is not finished")]
-    GameNotFinished,
-    #[msg("Invalid round index")]
-    InvalidRound,
-    #[msg("Invalid move")]
-    InvalidMove,
-    #[msg("Player is not part of this game")]
-    NotAPlayer,
-    #[msg("Already committed for this round")]
-    AlreadyCommitted,
-    #[msg("Not committed yet for this round")]
-    NotCommittedYet,
-    #[msg("Already revealed for this round")]
-    AlreadyRevealed,
-    #[msg("Commitment hash does not match")]
-    CommitmentMismatch,
-    #[msg("Math overflow")]
-    MathOverflow,
-    #[msg("Invalid game state")]
-    InvalidGameState,
-    #[msg("Invalid house wallet")]
-    InvalidHouseWallet,
-    #[msg("Invalid player account")]
-    InvalidPlayerAccount,
-    #[msg("Unauthorized admin")]
-    Unauthorized,
-    #[msg("Game not cancellable in this state")]
-    GameNotCancellable,
-    #[msg("Game has not timed out yet")]
-    NotTimedOut,
-    #[msg("Invalid house fee bps")]
-    InvalidHouseFee,
-    #[msg("Bet is below minimum allowed")]
-    BetTooLow,
-    #[msg("Commit phase for this round has expired")]
-    CommitPhaseExpired,
-    #[msg("Both players must commit before reveal")]
-    BothMustCommitFirst,
-    #[msg("Round already resolved")]
-    RoundAlreadyResolved,
-    #[msg("Commit window not started for this round")]
-    CommitWindowNotStarted,
-    #[msg("Commit phase not yet expired")]
-    CommitPhaseNotExpired,
-    #[msg("Both players committed, timeout resolve not allowed")]
-    BothCommittedNoTimeout,
-    #[msg("Commit window already started for this round")]
-    CommitWindowAlreadyStarted,
+        // a partial entry fee refund fails the whole force refund
+        let (result, _, _) = force_refund(1_500);
+        assert_eq!(result, Err(program_error(RpsError::InsufficientHouseFunds)));
+    }
+
+    #[test]
+    fn unrevealed_match_root_rounds_time_out_against_the_silent_seat() {
+        let game_id = [5u8; 32];
+        let (player1, player2) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let leaves = |player: &Pubkey| -> Vec<_> {
+            (0..MAX_ROUNDS as u8)
+                .map(|round| move_commitment(0, &[round; 32], &game_id, round, player))
+                .collect()
+        };
+        let (leaves_p1, leaves_p2) = (leaves(&player1), leaves(&player2));
+        let house_vault = house_vault_with(|_| {});
+        let (game_info, _) = game_accounts(game_with(|game| {
+            game.game_id = game_id;
+            game.status = GameStatus::Active;
+            game.player1 = player1;
+            game.player2 = player2;
+            game.house_vault = house_vault.key();
+            game.match_root_p1 = match_tree(&leaves_p1, 0).0;
+            game.match_root_p2 = match_tree(&leaves_p2, 0).0;
+        }));
+
+        let start_round = |round_index: u8| {
+            process(
+                crate::instruction::StartRound { round_index },
+                &[game_info, signer(player1), house_vault, omitted()],
+            )
+        };
+        let reveal = |player: Pubkey, leaves: &[[u8; 32]], round_index: u8| {
+            process(
+                crate::instruction::RevealMoveWithProof {
+                    round_index,
+                    move_value: 0,
+                    nonce: [round_index; 32],
+                    proof: match_tree(leaves, round_index as usize).1,
+                },
+                &[game_info, signer(player), omitted()],
+            )
+        };
+        let resolve = |instruction: Vec<u8>| {
+            let accounts = [signer(Pubkey::new_unique()).clone(), (*game_info).clone()];
+            crate::entry(&crate::ID, Box::leak(Box::new(accounts)), &instruction)
+        };
+        let commit_timeout = |round_index| {
+            resolve(crate::instruction::ResolveCommitTimeout { round_index }.data())
+        };
+        let reveal_timeout = |round_index| {
+            resolve(crate::instruction::ResolveRevealTimeout { round_index }.data())
+        };
+
+        // both roots commit both seats, so only the reveal window can run out
+        set_clock(1_000, 0);
+        start_round(0).unwrap();
+        let reveal_deadline = 1_000 + REVEAL_PHASE_SLOTS;
+        assert_eq!(load::<Game>(game_info).reveal_deadline_slots[0], reveal_deadline);
+        reveal(player1, &leaves_p1, 0).unwrap();
+        set_clock(reveal_deadline, 0);
+        assert_eq!(reveal_timeout(0), Err(program_error(RpsError::RevealPhaseNotExpired)));
+
+        set_clock(reveal_deadline + 1, 0);
+        assert_eq!(commit_timeout(0), Err(program_error(RpsError::BothCommittedNoTimeout)));
+        assert_eq!(
+            reveal(player2, &leaves_p2, 0),
+            Err(program_error(RpsError::RevealPhaseExpired))
+        );
+        reveal_timeout(0).unwrap();
+        let game: Game = load(game_info);
+        assert_eq!((game.player1_wins, game.player2_wins, game.rounds_played), (1, 0, 1));
+        assert_eq!(reveal_timeout(0), Err(program_error(RpsError::RoundAlreadyResolved)));
+
+        // nobody reveals: the round is used up as a draw
+        start_round(1).unwrap();
+        set_clock(reveal_deadline + 2 + REVEAL_PHASE_SLOTS, 0);
+        reveal_timeout(1).unwrap();
+        let game: Game = load(game_info);
+        assert_eq!((game.player1_wins, game.player2_wins, game.rounds_played), (1, 0, 2));
+    }
 }